@@ -0,0 +1,771 @@
+use anyhow::{Context, Result, bail};
+use common::{
+    args::{self, Help},
+    errors::{self, ErrorFormat, ErrorReport},
+    vprintln,
+};
+use lexopt::{Arg, ValueExt};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    io::Read,
+};
+
+struct Args {
+    filenames: Vec<String>,
+    skipcheck: bool,
+    output_dir: Option<String>,
+    name_from_content: bool,
+    report: Option<String>,
+    max_entries: u32,
+    verbose: bool,
+    quiet: bool,
+    range: Option<EntryRange>,
+    only: Option<String>,
+    entry_prefix: Option<String>,
+    no_stem: bool,
+    limit: Option<usize>,
+    hash: bool,
+    align: u64,
+    sort: SortKey,
+    deep_scan: bool,
+    error_format: ErrorFormat,
+    header_offset: u64,
+    no_realign: bool,
+    hexdump_on_error: bool,
+    pack: Option<String>,
+    pad_to: u64,
+}
+
+impl Args {
+    /// What entry names start with in place of `<stem>.`, from `--entry-prefix` or `--no-stem`.
+    fn entry_prefix(&self) -> Option<&str> {
+        if self.no_stem {
+            Some("")
+        } else {
+            self.entry_prefix.as_deref()
+        }
+    }
+}
+
+/// An inclusive range of entry indices, given on the command line as `A:B`.
+#[derive(Clone, Copy, Debug)]
+struct EntryRange {
+    first: u32,
+    last: u32,
+}
+
+impl std::str::FromStr for EntryRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s
+            .split_once(':')
+            .ok_or_else(|| format!("invalid range '{}', expected <first>:<last>", s))?;
+        let first: u32 = first.parse().map_err(|e| format!("invalid range start '{}': {}", first, e))?;
+        let last: u32 = last.parse().map_err(|e| format!("invalid range end '{}': {}", last, e))?;
+        if first > last {
+            return Err(format!("invalid range '{}', the start is after the end", s));
+        }
+        Ok(EntryRange { first, last })
+    }
+}
+
+/// The order entries are processed and reported in, from `--sort`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Index,
+    /// Largest first.
+    Size,
+    /// By the name embedded in the entry, entries without one go last.
+    Name,
+    /// By detected type extension.
+    Type,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "index" => Ok(SortKey::Index),
+            "size" => Ok(SortKey::Size),
+            "name" => Ok(SortKey::Name),
+            "type" => Ok(SortKey::Type),
+            _ => Err(format!("unknown sort order '{}', expected index, size, name or type", s)),
+        }
+    }
+}
+
+const HELP: Help = Help {
+    name: "binextract",
+    version: env!("CARGO_PKG_VERSION"),
+    usage: "[options] <binfile>...",
+    description: &[],
+    options: &[
+        ("-s, --skipcheck", "don't require the last entry to be 'PSPCHECK'"),
+        (
+            "--header-offset <n>",
+            "skip <n> bytes of preamble, the archive and its entry padding start there (see below)",
+        ),
+        ("--align <n>", "the archive pads its header and entries to <n> bytes, a power of two (default 16)"),
+        (
+            "--no-realign",
+            "only the header is padded, the entries follow each other (detected when only this way ends in 'PSPCHECK')",
+        ),
+        (
+            "--entry-prefix <str>",
+            "name entries <str><index>.<ext>, with a zero padded index, instead of <stem>.<index>.<ext>",
+        ),
+        ("--no-stem", "name entries <index>.<ext>, with a zero padded index"),
+        ("--name-from-content", "name entries after a filename embedded in them (GIM file info, VAG name)"),
+        ("--max-entries <n>", "treat archives with more than <n> entries as invalid (default 10000)"),
+        ("--only <ext>", "only extract entries detected as type <ext> (e.g. gim, vag, bin)"),
+        ("--range <a>:<b>", "only extract entries <a> to <b> inclusive"),
+        ("--report <path>", "write a JSON report of every archive to <path>"),
+        ("--sort <key>", "process entries by 'index' (default), 'size' (largest first), 'name' or 'type'"),
+        ("--hash", "add the SHA-256 of every extracted entry to the output and the report"),
+        ("--deep-scan", "also extract GIM files found inside entries that aren't GIMs themselves"),
+        (
+            "--pack <dir>",
+            "pack the files extracted to <dir> back into the archive given by -o, instead of extracting",
+        ),
+        (
+            "--pad-to <n>",
+            "with --pack, pad the header and entries to <n> bytes, a power of two (default the --align value)",
+        ),
+    ],
+    notes: &[
+        "Use '-' as the binfile to read the archive from stdin, extracted files are named 'stdin'.",
+        "With --pack the files are ordered by the index in their names, <stem>.<index>.<ext>, or <prefix><index>.<ext>",
+        "when --entry-prefix or --no-stem is given as for the extraction, and padded to --pad-to bytes. A new 'PSPCHECK'",
+        "entry is added last unless --skipcheck is given, so to get back an archive whose check entry has more than",
+        "the signature in it, extract and pack it with --skipcheck. Every entry, the last included, is padded.",
+        "With --header-offset the entries are laid out as if the archive started at <n>, but the offsets that are",
+        "printed and reported are from the start of the file.",
+    ],
+    short_output: true,
+    threads: false,
+};
+
+fn parse_args<I>(args: I) -> Result<Args, lexopt::Error>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let mut skipcheck = false;
+    let mut name_from_content = false;
+    let mut report = None;
+    let mut max_entries = 10000;
+    let mut range = None;
+    let mut only = None;
+    let mut entry_prefix = None;
+    let mut no_stem = false;
+    let mut hash = false;
+    let mut align = binlib::DEFAULT_ALIGN;
+    let mut sort = SortKey::Index;
+    let mut deep_scan = false;
+    let mut header_offset = 0;
+    let mut no_realign = false;
+    let mut pack = None;
+    let mut pad_to = None;
+    let common = args::parse(args, &HELP, |arg, parser| {
+        match arg {
+            Arg::Short('s') | Arg::Long("skipcheck") => {
+                skipcheck = true;
+            }
+            Arg::Long("max-entries") => {
+                max_entries = parser.value()?.parse()?;
+                if max_entries == 0 {
+                    return Err("--max-entries must be greater than 0".into());
+                }
+            }
+            Arg::Long("range") => {
+                range = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("only") => {
+                only = Some(parser.value()?.string()?.trim_start_matches('.').to_lowercase());
+            }
+            Arg::Long("entry-prefix") => {
+                entry_prefix = Some(parser.value()?.string()?);
+            }
+            Arg::Long("no-stem") => {
+                no_stem = true;
+            }
+            Arg::Long("align") => {
+                align = parser.value()?.parse()?;
+                binlib::check_alignment(align).map_err(|e| e.to_string())?;
+            }
+            Arg::Long("hash") => {
+                hash = true;
+            }
+            Arg::Long("sort") => {
+                sort = parser.value()?.parse()?;
+            }
+            Arg::Long("deep-scan") => {
+                deep_scan = true;
+            }
+            Arg::Long("header-offset") => {
+                header_offset = parser.value()?.parse()?;
+            }
+            Arg::Long("no-realign") => {
+                no_realign = true;
+            }
+            Arg::Long("pack") => {
+                pack = Some(parser.value()?.string()?);
+            }
+            Arg::Long("pad-to") => {
+                let value = parser.value()?.parse()?;
+                binlib::check_alignment(value).map_err(|e| e.to_string())?;
+                pad_to = Some(value);
+            }
+            Arg::Long("report") => {
+                report = Some(parser.value()?.string()?);
+            }
+            Arg::Long("name-from-content") => {
+                name_from_content = true;
+            }
+            _ => return Err(arg.unexpected()),
+        }
+        Ok(())
+    })?;
+
+    if common.files.is_empty() && pack.is_none() {
+        eprintln!("Error: No input file specified.");
+        std::process::exit(1);
+    }
+    if pack.is_some() && common.output.is_none() {
+        return Err("--pack needs the archive to write, given with -o".into());
+    }
+    if pad_to.is_some() && pack.is_none() {
+        return Err("--pad-to only applies when packing an archive with --pack".into());
+    }
+
+    Ok(Args {
+        filenames: common.files,
+        skipcheck,
+        output_dir: common.output,
+        name_from_content,
+        report,
+        max_entries,
+        verbose: common.verbose,
+        quiet: common.quiet,
+        range,
+        only,
+        entry_prefix,
+        no_stem,
+        limit: common.limit,
+        hash,
+        align,
+        sort,
+        deep_scan,
+        error_format: common.error_format,
+        header_offset,
+        no_realign,
+        hexdump_on_error: common.hexdump_on_error,
+        pack,
+        pad_to: pad_to.unwrap_or(align),
+    })
+}
+
+/// Runs binextract with the given command line arguments (not including the program name).
+pub fn run<I>(args: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    //pull all command args and treat the first like the input
+    let args = parse_args(args).context("Failed to parse command line")?;
+    if let Some(dir) = &args.pack {
+        return pack_directory(dir, &args);
+    }
+    let mut reports = Vec::new();
+    let mut failed = 0;
+    for filename in &args.filenames {
+        let mut report = ArchiveReport::default();
+        let result = process_archive(filename, &args, &mut report);
+        if let Err(e) = &result {
+            args.error_format.print(
+                format_args!("Error processing archive {}: {:#}", filename, e),
+                ErrorReport {
+                    file: filename,
+                    kind: error_kind(e),
+                    message: &format!("{:#}", e),
+                    offset: error_offset(e),
+                },
+            );
+        }
+        if result.is_err() || !report.failures.is_empty() {
+            failed += 1;
+        }
+        reports.push(report.to_json(filename, &result));
+    }
+
+    if let Some(report_path) = &args.report {
+        let json = serde_json::to_string_pretty(&reports).context("Failed to serialize report")?;
+        std::fs::write(report_path, json).with_context(|| format!("Failed to write report: {}", report_path))?;
+    }
+    if failed > 0 {
+        bail!("{} of {} archives had errors", failed, args.filenames.len());
+    }
+    Ok(())
+}
+
+/// What happened to a single archive, for `--report`.
+#[derive(Default)]
+struct ArchiveReport {
+    entries: Option<u32>,
+    extracted: u32,
+    failures: Vec<(u32, String)>,
+    output_dir: Option<std::path::PathBuf>,
+    files: Vec<ExtractedFile>,
+}
+
+/// An entry written out by binextract, for the `files` list of the report.
+struct ExtractedFile {
+    index: u32,
+    path: std::path::PathBuf,
+    offset: u64,
+    size: u64,
+    kind: &'static str,
+    /// The hex SHA-256 of the entry data, only with `--hash`.
+    sha256: Option<String>,
+}
+
+impl ArchiveReport {
+    fn to_json(&self, input: &str, result: &Result<()>) -> serde_json::Value {
+        let failures: Vec<_> = self
+            .failures
+            .iter()
+            .map(|(index, error)| serde_json::json!({ "index": index, "error": error }))
+            .collect();
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| {
+                serde_json::json!({
+                    "index": file.index,
+                    "path": file.path.display().to_string(),
+                    "offset": file.offset,
+                    "size": file.size,
+                    "type": file.kind,
+                    "sha256": file.sha256,
+                })
+            })
+            .collect();
+        let mut value = serde_json::json!({
+            "input": input,
+            "status": if result.is_ok() && self.failures.is_empty() { "ok" } else { "error" },
+            "entries": self.entries,
+            "extracted": self.extracted,
+            "failures": failures,
+            "output_dir": self.output_dir.as_ref().map(|p| p.display().to_string()),
+            "files": files,
+        });
+        if let Err(e) = result {
+            value["error"] = serde_json::Value::String(format!("{:#}", e));
+        }
+        value
+    }
+}
+
+fn process_archive(input_file: &str, args: &Args, report: &mut ArchiveReport) -> Result<()> {
+    //the whole archive is read into memory, stdin included, as the entries need random access
+    let data = if input_file == "-" {
+        let mut data = Vec::new();
+        std::io::stdin().read_to_end(&mut data).context("Failed to read stdin")?;
+        data
+    } else {
+        std::fs::read(input_file).with_context(|| format!("Failed to read input file: {}", input_file))?
+    };
+
+    let num_entries = binlib::entry_count(&data, args.header_offset).context("Failed to read number of entries")?;
+    vprintln!(!args.quiet, "Number of entries: {}", num_entries);
+    report.entries = Some(num_entries);
+
+    let mut options = binlib::ParseOptions {
+        max_entries: args.max_entries,
+        skip_check: args.skipcheck,
+        align: args.align,
+        header_offset: args.header_offset,
+        realign: !args.no_realign,
+    };
+    if options.realign && binlib::looks_packed(&data, &options) {
+        vprintln!(
+            !args.quiet,
+            "NOTE: the entries only fit in the file unpadded, with 'PSPCHECK' last, reading them in packed mode as with --no-realign"
+        );
+        options.realign = false;
+    }
+    let archive = binlib::parse_archive_with(&data, &options).inspect_err(|e| {
+        if let Some(offset) = error_offset(e) {
+            hexdump_at(&data, offset as usize, args);
+        }
+    })?;
+    let num_files = archive.len() as u32;
+    //without the signature check a short archive still parses, say so once rather than only for each entry
+    if let Some(expected) = archive.iter().map(binlib::Entry::end).max()
+        && expected > data.len() as u64
+    {
+        eprintln!("WARNING: archive truncated: expected {} bytes, file is {} bytes", expected, data.len());
+    }
+
+    let mut entries: Vec<u32> = match args.range {
+        Some(range) if range.last >= num_files => {
+            bail!("Range {}:{} is out of bounds, the archive has {} files", range.first, range.last, num_files);
+        }
+        Some(range) => (range.first..=range.last).collect(),
+        None => (0..num_files).collect(),
+    };
+    //the sort is stable so ties stay in index order, and output names keep the original index
+    match args.sort {
+        SortKey::Index => {}
+        SortKey::Size => entries.sort_by_key(|&i| std::cmp::Reverse(archive[i as usize].length)),
+        SortKey::Name => entries.sort_by_cached_key(|&i| {
+            let name = archive[i as usize].data.and_then(detect_embedded_name);
+            (name.is_none(), name)
+        }),
+        SortKey::Type => entries.sort_by_cached_key(|&i| archive[i as usize].detect_type().extension()),
+    }
+
+    let input_name = if input_file == "-" {
+        OsStr::new("stdin")
+    } else {
+        std::path::Path::new(input_file)
+            .file_stem()
+            .with_context(|| format!("Input file {} has no file name", input_file))?
+    };
+    let mut output_dir = std::path::PathBuf::new();
+    if let Some(ref dir) = args.output_dir {
+        output_dir = std::path::PathBuf::from(dir);
+        std::fs::create_dir_all(&output_dir)
+            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+    }
+    if num_files > 1 {
+        // make a directory for the extracted files with the name of the input file without extension
+        output_dir.push(input_name);
+        std::fs::create_dir_all(&output_dir)
+            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+    }
+    report.output_dir = Some(output_dir.clone());
+
+    let entry_prefix = args.entry_prefix();
+    let index_width = (num_files.max(1) - 1).to_string().len();
+
+    let mut used_names = HashSet::new();
+    //entries skipped by --only don't count towards --limit, failed ones do
+    let mut attempted = 0;
+    for i in entries {
+        if args.limit.is_some_and(|limit| attempted == limit) {
+            vprintln!(args.verbose, "Stopping after {} entries (--limit)", attempted);
+            break;
+        }
+        let entry = &archive[i as usize];
+        vprintln!(!args.quiet, "Processing file {} - offset: 0x{:X} size: 0x{:X}", i, entry.offset, entry.length);
+
+        let Some(file_data) = entry.data else {
+            let message = format!("Entry is beyond the end of the archive ({} bytes)", data.len());
+            args.error_format.print(
+                format_args!("Error: File {} is beyond the end of the archive ({} bytes)", i, data.len()),
+                ErrorReport {
+                    file: &format!("{}:{}", input_file, i),
+                    kind: "truncated_entry",
+                    message: &message,
+                    offset: Some(entry.offset),
+                },
+            );
+            hexdump_at(&data, entry.offset as usize, args);
+            report.failures.push((i, message));
+            attempted += 1;
+            continue;
+        };
+
+        let file_type = entry.detect_type();
+        let suffix = file_type.extension();
+        if args.only.as_ref().is_some_and(|only| only != suffix) {
+            vprintln!(args.verbose, "Skipping file {} ({})", i, file_type.description());
+            continue;
+        }
+        vprintln!(args.verbose, "File {} type: {}", i, file_type.description());
+        attempted += 1;
+        let mut output_path = std::path::PathBuf::from(&output_dir); // use specified output directory
+        //only take an embedded name once per archive so duplicates fall back to the index scheme
+        let content_name = if args.name_from_content {
+            detect_embedded_name(file_data).filter(|name| used_names.insert(name.clone()))
+        } else {
+            None
+        };
+        if let Some(name) = content_name {
+            output_path.push(name); //use the embedded name as base name
+            output_path.add_extension(suffix); //add suffix as extension
+        } else if let Some(prefix) = &entry_prefix {
+            //custom prefixes get a zero padded index so the names sort in entry order
+            output_path.push(format!("{}{:0width$}.{}", prefix, i, suffix, width = index_width));
+        } else {
+            //a single entry keeps its index too, so --pack can put the archive back together
+            output_path.push(input_name); //add input file stem as base name
+            output_path.add_extension(format!("{}.{}", i, suffix)); //add index and suffix as extension
+        }
+        if let Err(e) = std::fs::write(&output_path, file_data) {
+            let message = format!("Failed to write output file {}: {}", output_path.display(), e);
+            args.error_format.print(
+                format_args!("Error: {}", message),
+                ErrorReport {
+                    file: &format!("{}:{}", input_file, i),
+                    kind: "io",
+                    message: &message,
+                    offset: Some(entry.offset),
+                },
+            );
+            report.failures.push((i, message));
+            continue;
+        }
+        //hashing is opt in as it costs a pass over every entry
+        let sha256 = args.hash.then(|| format!("{:x}", Sha256::digest(file_data)));
+        match &sha256 {
+            Some(sha256) => {
+                vprintln!(!args.quiet, "Extracted file {}: {} bytes sha256 {}", output_path.display(), entry.length, sha256)
+            }
+            None => vprintln!(!args.quiet, "Extracted file {}: {} bytes", output_path.display(), entry.length),
+        }
+        report.extracted += 1;
+        if args.deep_scan && file_type != binlib::FileType::Gim {
+            extract_embedded_gims(file_data, entry.offset, i, &output_path, args, report);
+        }
+        report.files.push(ExtractedFile {
+            index: i,
+            path: output_path,
+            offset: entry.offset,
+            size: entry.length,
+            kind: suffix,
+            sha256,
+        });
+    }
+    Ok(())
+}
+
+/// Packs the files extracted to `dir` into the archive named by `-o`, for `--pack`. Files without an entry index in
+/// their name, such as those from `--deep-scan`, are left out.
+fn pack_directory(dir: &str, args: &Args) -> Result<()> {
+    if args.no_realign {
+        bail!("--pack only writes archives with every entry padded, it can't be used with --no-realign");
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read directory {}", dir))? {
+        let path = entry.with_context(|| format!("Failed to read directory {}", dir))?.path();
+        if !path.is_file() {
+            continue;
+        }
+        match entry_index(&path, args.entry_prefix()) {
+            Some(index) => files.push((index, path)),
+            None => vprintln!(!args.quiet, "Skipping {}, it has no entry index in its name", path.display()),
+        }
+    }
+    files.sort();
+    //a gap or a repeated index would shift every entry after it
+    for (expected, (index, path)) in files.iter().enumerate() {
+        if *index != expected as u32 {
+            bail!("Entry {} is missing or repeated, found {} (entry {}) in its place", expected, path.display(), index);
+        }
+    }
+    if files.is_empty() {
+        bail!("No numbered entries found in {}", dir);
+    }
+
+    let entries = files
+        .iter()
+        .map(|(_, path)| std::fs::read(path).with_context(|| format!("Failed to read {}", path.display())))
+        .collect::<Result<Vec<_>>>()?;
+    let entries: Vec<&[u8]> = entries.iter().map(Vec::as_slice).collect();
+    let options = binlib::PackOptions {
+        pad_to: args.pad_to,
+        skip_check: args.skipcheck,
+    };
+    let archive = binlib::pack_archive(&entries, &options)?;
+
+    //-o names the archive here rather than a directory
+    let output = args.output_dir.as_deref().context("No archive given with -o")?;
+    std::fs::write(output, &archive).with_context(|| format!("Failed to write archive {}", output))?;
+    vprintln!(!args.quiet, "Packed {} entries into {}: {} bytes", entries.len(), output, archive.len());
+    Ok(())
+}
+
+/// The entry index in the name of an extracted file, named as extraction names them: `<stem>.<index>.<ext>`, or
+/// `<prefix><index>.<ext>` with `--entry-prefix` or `--no-stem`. Anything else, such as a name taken from the
+/// content or a GIM found by `--deep-scan`, has no index.
+fn entry_index(path: &std::path::Path, entry_prefix: Option<&str>) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = match entry_prefix {
+        Some(prefix) => stem.strip_prefix(prefix)?,
+        None => stem.rsplit_once('.')?.1,
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The kind of an archive error for error reports, from the [`binlib::ArchiveError`] behind it when there is one.
+fn error_kind(e: &anyhow::Error) -> &'static str {
+    if e.chain().any(|cause| cause.is::<std::io::Error>()) {
+        "io"
+    } else {
+        e.chain()
+            .find_map(|cause| cause.downcast_ref::<binlib::ArchiveError>())
+            .map_or("archive", |e| e.kind.name())
+    }
+}
+
+/// The offset in the input file that an archive error is about, for the errors that have one.
+fn error_offset(e: &anyhow::Error) -> Option<u64> {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<binlib::ArchiveError>().map(|e| e.offset))
+}
+
+/// Prints the bytes of the archive around `offset` for `--hexdump-on-error`.
+fn hexdump_at(data: &[u8], offset: usize, args: &Args) {
+    if args.hexdump_on_error {
+        eprint!("Bytes around offset 0x{:X}:\n{}", offset, errors::hexdump(data, offset, 0));
+    }
+}
+
+/// Writes out every GIM file found inside an entry, for `--deep-scan`. They are named after the entry with the
+/// offset of the GIM within it in hex, e.g. `movie.3.0x1A0.gim` for a GIM at 0x1A0 in `movie.3.pmf`. The `0x`
+/// keeps offsets that are all digits from being read as an entry index by `--pack`.
+fn extract_embedded_gims(
+    file_data: &[u8],
+    entry_offset: u64,
+    index: u32,
+    entry_path: &std::path::Path,
+    args: &Args,
+    report: &mut ArchiveReport,
+) {
+    for (offset, gim) in find_embedded_gims(file_data) {
+        let output_path = entry_path.with_extension(format!("0x{:X}.gim", offset));
+        vprintln!(args.verbose, "Found GIM in file {} at offset 0x{:X}, {} bytes", index, offset, gim.len());
+        if let Err(e) = std::fs::write(&output_path, &gim) {
+            let message = format!("Failed to write output file {}: {}", output_path.display(), e);
+            args.error_format.print(
+                format_args!("Error: {}", message),
+                ErrorReport {
+                    file: &output_path.display().to_string(),
+                    kind: "io",
+                    message: &message,
+                    offset: Some(entry_offset + offset as u64),
+                },
+            );
+            report.failures.push((index, message));
+            continue;
+        }
+        vprintln!(!args.quiet, "Extracted embedded GIM {}: {} bytes", output_path.display(), gim.len());
+        report.files.push(ExtractedFile {
+            index,
+            path: output_path,
+            offset: entry_offset + offset as u64,
+            size: gim.len() as u64,
+            kind: "gim",
+            sha256: args.hash.then(|| format!("{:x}", Sha256::digest(&gim))),
+        });
+    }
+}
+
+/// Searches data for the GIM signature and returns the offset and data of every complete GIM file found.
+fn find_embedded_gims(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(pos) = data[offset..]
+        .windows(4)
+        .position(|magic| magic == b"MIG." || magic == b".GIM")
+    {
+        let start = offset + pos;
+        match gim::file_size(&data[start..]) {
+            //copied out as loading the GIM needs its headers aligned, which a GIM at any offset may not be
+            Ok(size) if size > 0 => {
+                found.push((start, data[start..start + size].to_vec()));
+                offset = start + size;
+            }
+            _ => offset = start + 1,
+        }
+    }
+    found
+}
+
+/// Looks for an original filename stored inside the entry, for the formats that carry one.
+/// Only the file stem is kept and anything that is not safe in a filename is dropped.
+fn detect_embedded_name(file_data: &[u8]) -> Option<String> {
+    let raw_name = match file_data.get(0..4) {
+        Some(b"MIG." | b".GIM") => gim_file_info_name(file_data)?,
+        Some(b"VAGp") => {
+            //the VAG header has a 16 byte name field at 0x20
+            let field = file_data.get(0x20..0x30)?;
+            field.split(|b| *b == 0).next()?
+        }
+        _ => return None,
+    };
+
+    let raw_name = String::from_utf8_lossy(raw_name);
+    let file_name = raw_name.rsplit(['/', '\\']).next()?;
+    let stem = file_name.split_once('.').map_or(file_name, |(stem, _)| stem);
+    let name: String = stem
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '))
+        .collect();
+    let name = name.trim();
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+/// Returns the original filename from the FILE_INFO chunk of a GIM, which is the first of its strings.
+fn gim_file_info_name(file_data: &[u8]) -> Option<&[u8]> {
+    let mut info = None;
+    //a malformed chunk later in the file doesn't lose a name that was already found
+    let _ = gim::walk_chunks(file_data, |chunk| {
+        if chunk.kind == gim::GimChunkKind::FileInfo && info.is_none() {
+            info = Some(chunk.offset + chunk.data_offs as usize..chunk.offset + chunk.next_offs as usize);
+        }
+        Ok(())
+    });
+    file_data.get(info?)?.split(|b| *b == 0).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn entry_index_reads_the_index_before_the_extension() {
+        assert_eq!(entry_index(Path::new("out/map01.0.gim"), None), Some(0));
+        assert_eq!(entry_index(Path::new("out/map01.12.bin"), None), Some(12));
+        assert_eq!(entry_index(Path::new("out/map01.gim"), None), None);
+        assert_eq!(entry_index(Path::new("out/map01.x1.gim"), None), None);
+    }
+
+    #[test]
+    fn entry_index_reads_the_index_after_the_prefix() {
+        assert_eq!(entry_index(Path::new("out/file_007.gim"), Some("file_")), Some(7));
+        assert_eq!(entry_index(Path::new("out/42.gim"), Some("")), Some(42));
+        assert_eq!(entry_index(Path::new("out/other_007.gim"), Some("file_")), None);
+        assert_eq!(entry_index(Path::new("out/logo.gim"), Some("")), None);
+    }
+
+    #[test]
+    fn archive_errors_report_their_kind_and_offset() {
+        let options = binlib::ParseOptions {
+            header_offset: 8,
+            ..Default::default()
+        };
+        let e = binlib::parse_archive_with(&[0; 4], &options)
+            .unwrap_err()
+            .context("Failed to parse");
+        assert_eq!((error_kind(&e), error_offset(&e)), ("bad_header", Some(8)));
+        let e = anyhow::anyhow!("Range is out of bounds");
+        assert_eq!((error_kind(&e), error_offset(&e)), ("archive", None));
+    }
+
+    #[test]
+    fn entry_index_skips_deep_scan_outputs() {
+        assert_eq!(entry_index(Path::new("out/movie.3.0x100.gim"), None), None);
+        assert_eq!(entry_index(Path::new("out/movie.3.0x1A0.gim"), None), None);
+    }
+}
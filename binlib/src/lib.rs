@@ -0,0 +1,660 @@
+//! Reading of the SMT:DS PSP .bin archives.
+//!
+//! An archive starts with a little endian u32 count of entries, followed by a u32 length for each entry.
+//! The entry data follows the header, each entry starting on a 16 byte boundary (some games use a larger
+//! power of two). The game checks that the last entry is the string 'PSPCHECK', which is not counted as one
+//! of the files.
+
+use anyhow::{Result, bail};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// The boundary the header and every entry are padded to, unless the archive uses another.
+pub const DEFAULT_ALIGN: u64 = 16;
+
+/// The signature stored as the last entry of an archive.
+pub const CHECK_SIGNATURE: &[u8] = b"PSPCHECK";
+
+/// How strictly [`parse_archive_with`] checks the archive.
+#[derive(Clone, Copy, Debug)]
+pub struct ParseOptions {
+    /// Archives with more entries than this are treated as invalid.
+    pub max_entries: u32,
+    /// Don't require the last entry to be the 'PSPCHECK' signature.
+    pub skip_check: bool,
+    /// The boundary the header and entries are padded to, a power of two.
+    pub align: u64,
+    /// The number of bytes before the entry count, for archives with a preamble. The archive, and the alignment
+    /// of its entries, starts there, but the offsets of the returned entries are from the start of the data.
+    pub header_offset: u64,
+    /// Pad every entry to `align` bytes. When not set the entries follow each other directly after the padded
+    /// header, for archives whose stored lengths already take up the padding.
+    pub realign: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_entries: 10000,
+            skip_check: false,
+            align: DEFAULT_ALIGN,
+            header_offset: 0,
+            realign: true,
+        }
+    }
+}
+
+/// How [`pack_archive`] lays out the archive.
+#[derive(Clone, Copy, Debug)]
+pub struct PackOptions {
+    /// The boundary the header and entries are padded to, a power of two.
+    pub pad_to: u64,
+    /// Don't add the 'PSPCHECK' signature as the last entry.
+    pub skip_check: bool,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            pad_to: DEFAULT_ALIGN,
+            skip_check: false,
+        }
+    }
+}
+
+/// Checks that an alignment is usable for an archive, which means a power of two.
+pub fn check_alignment(align: u64) -> Result<()> {
+    if !align.is_power_of_two() {
+        bail!("Alignment {} is not a power of two", align);
+    }
+    Ok(())
+}
+
+/// What is wrong with an archive that can't be parsed, see [`ArchiveError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveErrorKind {
+    /// The entry count makes no sense, or the header offset it is read from is past the end of the file.
+    BadHeader,
+    /// The file ends inside the header or the last entry.
+    Truncated,
+    /// The last entry is not the 'PSPCHECK' signature.
+    MissingCheck,
+}
+
+impl ArchiveErrorKind {
+    /// A short name for the kind, for error reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            ArchiveErrorKind::BadHeader => "bad_header",
+            ArchiveErrorKind::Truncated => "truncated",
+            ArchiveErrorKind::MissingCheck => "missing_check",
+        }
+    }
+}
+
+/// An error about the layout of an archive, with the offset in the file of the data it is about: the header for
+/// a bad entry count or a short header, and the last entry for a missing signature or a truncated file.
+#[derive(Debug)]
+pub struct ArchiveError {
+    pub kind: ArchiveErrorKind,
+    pub offset: u64,
+    message: String,
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+fn archive_error(kind: ArchiveErrorKind, offset: u64, message: String) -> anyhow::Error {
+    ArchiveError { kind, offset, message }.into()
+}
+
+/// A file stored in the archive.
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    pub offset: u64,
+    pub length: u64,
+    /// The entry data, `None` when the entry goes past the end of the archive.
+    pub data: Option<&'a [u8]>,
+}
+
+impl Entry<'_> {
+    /// The offset just past the entry data, unpadded.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+
+    /// The type of data in the entry, [`FileType::Unknown`] when it goes past the end of the archive.
+    pub fn detect_type(&self) -> FileType {
+        self.data.map_or(FileType::Unknown, detect_type)
+    }
+}
+
+/// The type of an entry, recognised from the magic at its start, with what could be read from its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// PSP image.
+    Gim,
+    /// MIDI audio.
+    Midi,
+    /// PSP audio program header.
+    Phd,
+    /// PSP movie, with the four digit version from its header (e.g. `0012`).
+    Psmf {
+        version: [u8; 4],
+    },
+    /// Playstation audio, with its sample rate.
+    Vag {
+        sample_rate: u32,
+    },
+    /// A RIFF container, with the form type that follows the size (e.g. `WAVE` or `AT3 `).
+    Riff {
+        form: [u8; 4],
+    },
+    /// Ogg audio.
+    Ogg,
+    /// ELF executable, `prx` when it is a PSP relocatable module.
+    Elf {
+        prx: bool,
+    },
+    /// PSP executable package, as an EBOOT.PBP or with the signed `~SCE` header in front.
+    Pbp,
+    /// ZIP archive.
+    Zip,
+    /// Bink video.
+    Bink,
+    /// PSP model.
+    Gmo,
+    Unknown,
+}
+
+impl FileType {
+    /// The file extension used for the type, 'bin' when it is not recognised.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileType::Gim => "gim",
+            FileType::Midi => "mid",
+            FileType::Phd => "phd",
+            FileType::Psmf { .. } => "pmf",
+            FileType::Vag { .. } => "vag",
+            FileType::Riff {
+                form: [b'W', b'A', b'V', b'E'],
+            } => "wav",
+            FileType::Riff {
+                form: [b'A', b'T', b'3', b' '],
+            } => "at3",
+            FileType::Riff { .. } => "riff",
+            FileType::Ogg => "ogg",
+            FileType::Elf { prx: true } => "prx",
+            FileType::Elf { prx: false } => "elf",
+            FileType::Pbp => "pbp",
+            FileType::Zip => "zip",
+            FileType::Bink => "bik",
+            FileType::Gmo => "gmo",
+            FileType::Unknown => "bin",
+        }
+    }
+
+    /// A short description of the type for display, including any header details.
+    pub fn description(&self) -> String {
+        match self {
+            FileType::Gim => "PSP image".to_string(),
+            FileType::Midi => "MIDI audio".to_string(),
+            FileType::Phd => "PSP audio header".to_string(),
+            FileType::Psmf { version } => format!("PSP movie (version {})", String::from_utf8_lossy(version)),
+            FileType::Vag { sample_rate } => format!("Playstation audio ({} Hz)", sample_rate),
+            FileType::Riff { form } => format!("RIFF {} container", String::from_utf8_lossy(form)),
+            FileType::Ogg => "Ogg audio".to_string(),
+            FileType::Elf { prx: true } => "PSP module".to_string(),
+            FileType::Elf { prx: false } => "ELF executable".to_string(),
+            FileType::Pbp => "PSP executable package".to_string(),
+            FileType::Zip => "ZIP archive".to_string(),
+            FileType::Bink => "Bink video".to_string(),
+            FileType::Gmo => "PSP model".to_string(),
+            FileType::Unknown => "unknown data".to_string(),
+        }
+    }
+}
+
+/// Returns the type of `file_data`, from the magic at its start.
+pub fn detect_type(file_data: &[u8]) -> FileType {
+    let bytes4 = |offset: usize| file_data.get(offset..offset + 4).map(|b| [b[0], b[1], b[2], b[3]]);
+    match file_data.get(0..4) {
+        //big endian GIMs have the signature the other way round
+        Some(b"MIG." | b".GIM") => FileType::Gim,
+        Some(b"MThd") => FileType::Midi,
+        Some(b"PPHD") => FileType::Phd,
+        Some(b"PSMF") => FileType::Psmf {
+            version: bytes4(4).unwrap_or_default(),
+        },
+        //the VAG header is big endian
+        Some(b"VAGp") => FileType::Vag {
+            sample_rate: bytes4(0x10).map_or(0, u32::from_be_bytes),
+        },
+        //WAV and AT3 are both RIFF, told apart by the form type
+        Some(b"RIFF") => FileType::Riff {
+            form: bytes4(8).unwrap_or_default(),
+        },
+        Some(b"OggS") => FileType::Ogg,
+        //PSP modules are ELFs with the PRX type, 0xFFA0
+        Some(b"\x7FELF") => FileType::Elf {
+            prx: file_data.get(16..18) == Some(&[0xA0, 0xFF]),
+        },
+        Some(b"~SCE" | b"\0PBP" | b"PBP\0") => FileType::Pbp,
+        Some(b"PK\x03\x04") => FileType::Zip,
+        //the fourth byte is the Bink version
+        Some([b'B', b'I', b'K', _]) => FileType::Bink,
+        //like GIMs, big endian models have the signature the other way round
+        Some(b"OMG." | b".GMO") => FileType::Gmo,
+        _ => FileType::Unknown,
+    }
+}
+
+/// Reads the number of entries from the header of the archive starting `header_offset` bytes into the data,
+/// including the 'PSPCHECK' entry.
+pub fn entry_count(data: &[u8], header_offset: u64) -> Result<u32> {
+    let Some(archive) = usize::try_from(header_offset).ok().and_then(|offset| data.get(offset..)) else {
+        return Err(archive_error(
+            ArchiveErrorKind::BadHeader,
+            header_offset,
+            format!("Header offset {} is beyond the end of the file ({} bytes)", header_offset, data.len()),
+        ));
+    };
+    match archive.get(0..4) {
+        Some(count) => Ok(LittleEndian::read_u32(count)),
+        None => Err(archive_error(
+            ArchiveErrorKind::Truncated,
+            header_offset,
+            format!("Archive is too small ({} bytes) to have a header", archive.len()),
+        )),
+    }
+}
+
+/// Parses the archive with the default [`ParseOptions`], returning its files.
+pub fn parse_archive(data: &[u8]) -> Result<Vec<Entry<'_>>> {
+    parse_archive_with(data, &ParseOptions::default())
+}
+
+/// Parses the archive, returning its files. The 'PSPCHECK' entry is checked and left out unless
+/// `skip_check` is set, in which case every entry is returned.
+pub fn parse_archive_with<'a>(data: &'a [u8], options: &ParseOptions) -> Result<Vec<Entry<'a>>> {
+    check_alignment(options.align)?;
+    let file_size = data.len() as u64;
+    let header_offset = options.header_offset;
+    let lengths = read_lengths(data, header_offset, options.max_entries)?;
+    //the header offset is within the data once the lengths have been read
+    let data = &data[header_offset as usize..];
+    let archive_size = data.len() as u64;
+    let num_entries = lengths.len();
+    let entry = |index: usize| {
+        let (offset, length) = if options.realign {
+            calc_offset_to_entry_aligned(index, &lengths, options.align)
+        } else {
+            calc_offset_to_entry_packed(index, &lengths, options.align)
+        };
+        let data = (offset + length <= archive_size).then(|| &data[offset as usize..(offset + length) as usize]);
+        Entry {
+            offset: header_offset + offset,
+            length,
+            data,
+        }
+    };
+
+    let mut num_files = num_entries;
+    if !options.skip_check {
+        //first check the last entry and see if it contains the string 'PSP CHECK'
+        let last_entry = entry(num_files - 1);
+        let Some(last_entry_data) = last_entry.data else {
+            return Err(archive_error(
+                ArchiveErrorKind::Truncated,
+                last_entry.offset,
+                format!("Archive truncated: expected {} bytes, file is {} bytes", last_entry.end(), file_size),
+            ));
+        };
+        if !last_entry_data.starts_with(CHECK_SIGNATURE) {
+            return Err(archive_error(
+                ArchiveErrorKind::MissingCheck,
+                last_entry.offset,
+                "Last entry is not a 'PSPCHECK' signature, invalid file.".to_string(),
+            ));
+        }
+        num_files -= 1;
+    }
+
+    Ok((0..num_files).map(entry).collect())
+}
+
+/// Reads the length of every entry from the header of the archive starting `header_offset` bytes into the data,
+/// checking the entry count is sensible.
+fn read_lengths(data: &[u8], header_offset: u64, max_entries: u32) -> Result<Vec<u32>> {
+    let num_entries = entry_count(data, header_offset)?;
+
+    //sanity check the number of entries
+    if num_entries == 0 || num_entries > max_entries {
+        return Err(archive_error(
+            ArchiveErrorKind::BadHeader,
+            header_offset,
+            format!("Suspicious number of entries: {} (maximum is {})", num_entries, max_entries),
+        ));
+    }
+    let data = &data[header_offset as usize..];
+    if 4 + num_entries as u64 * 4 > data.len() as u64 {
+        return Err(archive_error(
+            ArchiveErrorKind::Truncated,
+            header_offset,
+            format!("Archive is too small ({} bytes) for a header of {} entries", data.len(), num_entries),
+        ));
+    }
+
+    Ok(data[4..4 + num_entries as usize * 4]
+        .chunks_exact(4)
+        .map(LittleEndian::read_u32)
+        .collect())
+}
+
+/// Checks whether the archive looks like one with packed entries, whose last entry would run past the end of
+/// the archive with every entry padded to `align` but is the 'PSPCHECK' signature when they follow each other
+/// directly. `realign` and `skip_check` in the options are ignored, an archive without the signature is never
+/// taken to be packed.
+pub fn looks_packed(data: &[u8], options: &ParseOptions) -> bool {
+    let Ok(lengths) = read_lengths(data, options.header_offset, options.max_entries) else {
+        return false;
+    };
+    let data = &data[options.header_offset as usize..];
+    let last = lengths.len() - 1;
+    let (offset, length) = calc_offset_to_entry_aligned(last, &lengths, options.align);
+    if offset + length <= data.len() as u64 {
+        return false;
+    }
+    let (offset, length) = calc_offset_to_entry_packed(last, &lengths, options.align);
+    data.get(offset as usize..(offset + length) as usize)
+        .is_some_and(|entry| entry.starts_with(CHECK_SIGNATURE))
+}
+
+/// Returns the offset and length of the entry at `index`, given the lengths of every entry in the archive.
+pub fn calc_offset_to_entry(index: usize, lengths: &[u32]) -> (u64, u64) {
+    calc_offset_to_entry_aligned(index, lengths, DEFAULT_ALIGN)
+}
+
+/// Like [`calc_offset_to_entry`] for an archive padded to `align` bytes, which must be a power of two.
+pub fn calc_offset_to_entry_aligned(index: usize, lengths: &[u32], align: u64) -> (u64, u64) {
+    let mut offset = (4 + (lengths.len() as u64 * 4)).next_multiple_of(align);
+    for length in &lengths[..index] {
+        offset = (offset + *length as u64).next_multiple_of(align);
+    }
+
+    (offset, lengths[index] as u64)
+}
+
+/// Like [`calc_offset_to_entry_aligned`] for an archive whose entries are not padded, only the header is.
+pub fn calc_offset_to_entry_packed(index: usize, lengths: &[u32], align: u64) -> (u64, u64) {
+    let header = (4 + (lengths.len() as u64 * 4)).next_multiple_of(align);
+    let offset = header + lengths[..index].iter().map(|length| *length as u64).sum::<u64>();
+
+    (offset, lengths[index] as u64)
+}
+
+/// Builds an archive of `entries`, padding the header and each entry to `pad_to` bytes, which gives the offsets
+/// [`calc_offset_to_entry_aligned`] expects. The 'PSPCHECK' entry is added last unless `skip_check` is set.
+pub fn pack_archive(entries: &[&[u8]], options: &PackOptions) -> Result<Vec<u8>> {
+    check_alignment(options.pad_to)?;
+    let mut entries = entries.to_vec();
+    if !options.skip_check {
+        entries.push(CHECK_SIGNATURE);
+    }
+    let lengths = entries
+        .iter()
+        .map(|entry| u32::try_from(entry.len()))
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|_| anyhow::anyhow!("Entry is too large for an archive, the limit is 4GB"))?;
+    let count = u32::try_from(lengths.len()).map_err(|_| anyhow::anyhow!("Too many entries for an archive"))?;
+
+    let mut data = count.to_le_bytes().to_vec();
+    for length in &lengths {
+        data.extend_from_slice(&length.to_le_bytes());
+    }
+    //every entry is padded, the last one included
+    let pad_to = options.pad_to as usize;
+    data.resize(data.len().next_multiple_of(pad_to), 0);
+    for entry in &entries {
+        data.extend_from_slice(entry);
+        data.resize(data.len().next_multiple_of(pad_to), 0);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repack(data: &[u8], parse: &ParseOptions, pack: &PackOptions) -> Vec<u8> {
+        let entries = parse_archive_with(data, parse).unwrap();
+        let entries: Vec<&[u8]> = entries.iter().map(|entry| entry.data.unwrap()).collect();
+        pack_archive(&entries, pack).unwrap()
+    }
+
+    /// An archive header of `lengths`, without any entry data.
+    fn header(lengths: &[u32]) -> Vec<u8> {
+        let mut data = (lengths.len() as u32).to_le_bytes().to_vec();
+        for length in lengths {
+            data.extend_from_slice(&length.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn read_lengths_reads_every_length() {
+        assert_eq!(read_lengths(&header(&[5, 0, 8]), 0, 10).unwrap(), [5, 0, 8]);
+    }
+
+    #[test]
+    fn read_lengths_rejects_a_suspicious_count() {
+        assert!(read_lengths(&header(&[]), 0, 10).is_err());
+        assert!(read_lengths(&header(&[1; 11]), 0, 10).is_err());
+        assert!(read_lengths(&[1, 0], 0, 10).is_err());
+    }
+
+    #[test]
+    fn read_lengths_rejects_a_header_past_the_end() {
+        let mut data = header(&[1, 2, 3]);
+        data.truncate(10);
+        assert!(read_lengths(&data, 0, 10).is_err());
+    }
+
+    #[test]
+    fn aligned_offsets_pad_every_entry() {
+        let lengths = [5, 16, 17, 8];
+        let offsets: Vec<_> = (0..4).map(|i| calc_offset_to_entry_aligned(i, &lengths, 16)).collect();
+        assert_eq!(offsets, [(32, 5), (48, 16), (64, 17), (96, 8)]);
+        assert_eq!(calc_offset_to_entry(2, &lengths), (64, 17));
+        assert_eq!(calc_offset_to_entry_aligned(1, &lengths, 64), (128, 16));
+    }
+
+    #[test]
+    fn packed_offsets_only_pad_the_header() {
+        let lengths = [5, 16, 17, 8];
+        let offsets: Vec<_> = (0..4).map(|i| calc_offset_to_entry_packed(i, &lengths, 16)).collect();
+        assert_eq!(offsets, [(32, 5), (37, 16), (53, 17), (70, 8)]);
+    }
+
+    #[test]
+    fn looks_packed_when_only_the_packed_layout_fits() {
+        //three entries of 5 bytes directly after the 16 byte header, then the check entry
+        let mut data = header(&[5, 5, 5, 8]);
+        data.resize(32, 0);
+        data.extend_from_slice(&[1; 15]);
+        data.extend_from_slice(CHECK_SIGNATURE);
+        assert!(looks_packed(&data, &ParseOptions::default()));
+        let entries = parse_archive_with(
+            &data,
+            &ParseOptions {
+                realign: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].offset, 42);
+    }
+
+    #[test]
+    fn does_not_look_packed_without_the_check_entry() {
+        //the same layout as above, but the last entry is not the signature
+        let mut data = header(&[5, 5, 5, 8]);
+        data.resize(32, 0);
+        data.extend_from_slice(&[1; 15]);
+        data.extend_from_slice(b"NOTCHECK");
+        assert!(!looks_packed(&data, &ParseOptions::default()));
+    }
+
+    #[test]
+    fn does_not_look_packed_when_the_aligned_layout_fits() {
+        let data = pack_archive(&[&[1; 5], &[2; 5]], &PackOptions::default()).unwrap();
+        assert!(!looks_packed(&data, &ParseOptions::default()));
+        assert!(!looks_packed(&header(&[]), &ParseOptions::default()));
+    }
+
+    #[test]
+    fn parse_requires_the_check_entry() {
+        let data = pack_archive(
+            &[b"data", b"NOTCHECK"],
+            &PackOptions {
+                skip_check: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(parse_archive(&data).is_err());
+        let entries = parse_archive_with(
+            &data,
+            &ParseOptions {
+                skip_check: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn parse_rejects_a_truncated_archive() {
+        let mut data = pack_archive(&[b"data"], &PackOptions::default()).unwrap();
+        data.truncate(data.len() - 9);
+        let error = parse_archive(&data).unwrap_err().to_string();
+        assert!(error.starts_with("Archive truncated"), "{}", error);
+    }
+
+    /// The kind and offset of the [`ArchiveError`] that parsing `data` fails with.
+    fn parse_error(data: &[u8], options: &ParseOptions) -> (ArchiveErrorKind, u64) {
+        let error = parse_archive_with(data, options).unwrap_err();
+        let error = error.downcast_ref::<ArchiveError>().unwrap();
+        (error.kind, error.offset)
+    }
+
+    #[test]
+    fn parse_errors_carry_their_offset() {
+        let options = ParseOptions {
+            header_offset: 4,
+            ..Default::default()
+        };
+        let mut data = vec![0xEE; 4];
+        data.extend(pack_archive(&[b"data"], &PackOptions::default()).unwrap());
+        assert_eq!(parse_archive_with(&data, &options).unwrap().len(), 1);
+
+        //the check entry is at 16 + 16 in the archive, which starts 4 bytes in
+        let mut missing_check = data.clone();
+        missing_check[4 + 32] = b'X';
+        assert_eq!(parse_error(&missing_check, &options), (ArchiveErrorKind::MissingCheck, 36));
+        assert_eq!(parse_error(&data[..40], &options), (ArchiveErrorKind::Truncated, 36));
+        assert_eq!(parse_error(&data[..10], &options), (ArchiveErrorKind::Truncated, 4));
+        assert_eq!(parse_error(&data[..6], &options), (ArchiveErrorKind::Truncated, 4));
+        assert_eq!(parse_error(&data[..3], &options), (ArchiveErrorKind::BadHeader, 4));
+        let mut bad_count = data.clone();
+        bad_count[4..8].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(parse_error(&bad_count, &options), (ArchiveErrorKind::BadHeader, 4));
+    }
+
+    #[test]
+    fn parse_leaves_out_the_data_of_entries_past_the_end() {
+        let mut data = pack_archive(
+            &[b"data", &[1; 20]],
+            &PackOptions {
+                skip_check: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        data.truncate(data.len() - 16);
+        let entries = parse_archive_with(
+            &data,
+            &ParseOptions {
+                skip_check: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(entries[0].data, Some(&b"data"[..]));
+        assert_eq!(entries[1].data, None);
+    }
+
+    #[test]
+    fn pack_round_trips_a_parsed_archive() {
+        let original = pack_archive(&[b"first entry", b"", &[0xAB; 33]], &PackOptions::default()).unwrap();
+        assert_eq!(repack(&original, &ParseOptions::default(), &PackOptions::default()), original);
+    }
+
+    #[test]
+    fn pack_round_trips_a_single_entry() {
+        let original = pack_archive(&[b"only"], &PackOptions::default()).unwrap();
+        assert_eq!(parse_archive(&original).unwrap().len(), 1);
+        assert_eq!(repack(&original, &ParseOptions::default(), &PackOptions::default()), original);
+    }
+
+    #[test]
+    fn pack_round_trips_with_a_larger_alignment() {
+        let pack = PackOptions {
+            pad_to: 64,
+            ..Default::default()
+        };
+        let parse = ParseOptions {
+            align: 64,
+            ..Default::default()
+        };
+        let original = pack_archive(&[&[1; 70], &[2; 3]], &pack).unwrap();
+        assert_eq!(original.len(), 64 + 128 + 64 + 64);
+        assert_eq!(repack(&original, &parse, &pack), original);
+    }
+
+    #[test]
+    fn pack_keeps_a_longer_check_entry_with_skip_check() {
+        //the check entry has more than the signature in it, which only survives as an ordinary entry
+        let pack = PackOptions {
+            skip_check: true,
+            ..Default::default()
+        };
+        let original = pack_archive(&[b"data", b"PSPCHECK and more"], &pack).unwrap();
+        assert_ne!(repack(&original, &ParseOptions::default(), &PackOptions::default()), original);
+        let parse = ParseOptions {
+            skip_check: true,
+            ..Default::default()
+        };
+        assert_eq!(repack(&original, &parse, &pack), original);
+    }
+
+    #[test]
+    fn pack_rejects_an_alignment_that_is_not_a_power_of_two() {
+        assert!(
+            pack_archive(
+                &[b"data"],
+                &PackOptions {
+                    pad_to: 24,
+                    ..Default::default()
+                }
+            )
+            .is_err()
+        );
+    }
+}
@@ -29,14 +29,11 @@ fn parse_args() -> Result<Args, lexopt::Error> {
     }
 
     if filename.is_empty() {
-        eprint!("Error: No input file specified.\n");
+        eprintln!("Error: No input file specified.");
         std::process::exit(1);
     }
 
-    Ok(Args {
-        filename: filename,
-        read_size,
-    })
+    Ok(Args { filename, read_size })
 }
 
 struct FileOffset {
@@ -0,0 +1,301 @@
+//! Command line parsing shared by the tools, so the common flags and the help text look the same everywhere.
+//! Each tool hands its own flags to [`parse`] as a callback and gets the common ones back in [`CommonArgs`].
+//! Default flags can be set in a config file, see [`parse`].
+
+use crate::errors::ErrorFormat;
+use lexopt::{Arg, Parser, ValueExt};
+use std::{ffi::OsString, path::Path};
+
+/// The flags every tool accepts.
+#[derive(Debug, Default)]
+pub struct CommonArgs {
+    /// The positional arguments, in order, with the paths from any `--input-list` where the flag was given.
+    pub files: Vec<String>,
+    /// The directory to write output files to, from `--output` (or `-o`, see [`Help::short_output`]).
+    pub output: Option<String>,
+    pub verbose: bool,
+    pub quiet: bool,
+    /// The most input files or entries to process, from `--limit`.
+    pub limit: Option<usize>,
+    /// The number of worker threads from `--threads`, only accepted by tools with [`Help::threads`] set. When the
+    /// flag is not given, or is 0, this is the available parallelism of the machine.
+    pub threads: usize,
+    /// How failures are printed, from `--error-format`.
+    pub error_format: ErrorFormat,
+    /// Print the bytes around the offset of errors that have one, from `--hexdump-on-error`.
+    pub hexdump_on_error: bool,
+}
+
+/// Describes a tool for its `--help` and `--version` output.
+pub struct Help<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    /// What follows the tool name in the usage line, e.g. `[options] <files>...`.
+    pub usage: &'a str,
+    /// Lines printed between the usage line and the options.
+    pub description: &'a [&'a str],
+    /// The tool's own options as (flags, description) pairs, listed before the common ones.
+    pub options: &'a [(&'a str, &'a str)],
+    /// Lines printed after the options.
+    pub notes: &'a [&'a str],
+    /// Whether `-o` is short for `--output`. Tools that already use `-o` for a flag of their own leave it unset.
+    pub short_output: bool,
+    /// Whether the tool spreads its work over `--threads`. Tools that don't reject the flag and leave it out of
+    /// their help.
+    pub threads: bool,
+}
+
+const COMMON_OPTIONS: &[(&str, &str)] = &[
+    ("--config <path>", "Read default flags from <path> instead of .<tool>.toml"),
+    ("--output <dir>", "Write output files to <dir>"),
+    ("--limit <n>", "Stop after processing <n> input files or entries"),
+    (
+        "--threads <n>",
+        "Number of worker threads, used when --jobs is not given (default the number of CPUs)",
+    ),
+    ("--input-list <file>", "Also process the paths in <file>, one per line, '#' starts a comment"),
+    ("--error-format <f>", "Print errors as 'text' (default) or 'json', one object per line"),
+    ("--hexdump-on-error", "Print the bytes around the failing offset of errors tied to one"),
+    ("-q, --quiet", "Only print warnings and errors"),
+    ("-v, --verbose", "Enable verbose output"),
+    ("--version", "Show the version"),
+    ("-h, --help", "Show this help message"),
+];
+
+impl Help<'_> {
+    pub fn print(&self) {
+        println!("Usage: {} {}", self.name, self.usage);
+        for line in self.description {
+            println!("{}", line);
+        }
+        println!("Options:");
+        for &(flags, description) in self.options.iter().chain(COMMON_OPTIONS) {
+            if flags.starts_with("--threads") && !self.threads {
+                continue;
+            }
+            let flags = match flags {
+                "--output <dir>" if self.short_output => "-o, --output <dir>",
+                flags => flags,
+            };
+            println!("  {:<20} {}", flags, description);
+        }
+        for line in self.notes {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Parses the command line, handing every flag that is not a common one to `tool_arg` along with the parser
+/// so it can read values. `tool_arg` should return `arg.unexpected()` for anything it does not know.
+/// `--help` and `--version` print their output and exit.
+///
+/// Flags from the config file given by `--config`, or `.<tool>.toml` in the working directory if there is one,
+/// are parsed first so the command line overrides them. See [`load_config`] for the file format.
+pub fn parse<I, F>(args: I, help: &Help, mut tool_arg: F) -> Result<CommonArgs, lexopt::Error>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+    F: FnMut(Arg<'_>, &mut Parser) -> Result<(), lexopt::Error>,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let mut common = CommonArgs::default();
+
+    let default_path = format!(".{}.toml", help.name);
+    let config_path = match config_arg(&args) {
+        Some(path) => Some(path),
+        None => Path::new(&default_path).is_file().then(|| default_path.into()),
+    };
+    if let Some(path) = config_path {
+        let flags = load_config(Path::new(&path))?;
+        //every key becomes a flag, so the only inputs from the config file are those of its input-list, which come
+        //before the ones on the command line
+        parse_into(&mut common, Parser::from_args(flags), help, &mut tool_arg)
+            .map_err(|e| format!("in config file {}: {}", path.display(), e))?;
+    }
+
+    parse_into(&mut common, Parser::from_args(args), help, &mut tool_arg)?;
+    if common.threads == 0 {
+        common.threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    }
+    Ok(common)
+}
+
+/// Finds the value of `--config` on the command line, which has to be known before anything else is parsed.
+fn config_arg(args: &[OsString]) -> Option<std::path::PathBuf> {
+    let mut config = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            config = args.next().map(Into::into);
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            config = Some(path.into());
+        }
+    }
+    config
+}
+
+/// Reads a config file of default flags and turns it into command line arguments. Each key is a long flag name
+/// without the dashes, e.g. `output = "png"` or `limit = 10`. `true` passes a flag that takes no value and
+/// `false` leaves it out, so such flags can't be turned back off from the command line.
+pub fn load_config(path: &Path) -> Result<Vec<OsString>, lexopt::Error> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("failed to read config file {}: {}", path.display(), e))?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("failed to parse config file {}: {}", path.display(), e))?;
+
+    let mut flags = Vec::new();
+    for (key, value) in table {
+        if matches!(key.as_str(), "config" | "help" | "version") {
+            return Err(format!("'{}' can't be set in config file {}", key, path.display()).into());
+        }
+        let value = match value {
+            toml::Value::Boolean(true) => {
+                flags.push(format!("--{}", key).into());
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            _ => return Err(format!("unsupported value for '{}' in config file {}", key, path.display()).into()),
+        };
+        //joined with = so values that start with a dash aren't taken for flags
+        flags.push(format!("--{}={}", key, value).into());
+    }
+    Ok(flags)
+}
+
+/// Reads the input paths from an `--input-list` file, one per line. Blank lines and lines starting with `#` are
+/// skipped, and surrounding whitespace is trimmed.
+pub fn read_input_list(path: &Path) -> Result<Vec<String>, lexopt::Error> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("failed to read input list {}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+fn parse_into<F>(common: &mut CommonArgs, mut parser: Parser, help: &Help, tool_arg: &mut F) -> Result<(), lexopt::Error>
+where
+    F: FnMut(Arg<'_>, &mut Parser) -> Result<(), lexopt::Error>,
+{
+    while let Some(arg) = parser.next()? {
+        //copy the long flag name out of the parser so values can still be read from it
+        let long;
+        let arg = match arg {
+            Arg::Long(name) => {
+                long = name.to_owned();
+                Arg::Long(&long)
+            }
+            Arg::Short(c) => Arg::Short(c),
+            Arg::Value(val) => Arg::Value(val),
+        };
+        match arg {
+            Arg::Short('h') | Arg::Long("help") => {
+                help.print();
+                std::process::exit(0);
+            }
+            Arg::Long("version") => {
+                println!("{} {}", help.name, help.version);
+                std::process::exit(0);
+            }
+            Arg::Short('q') | Arg::Long("quiet") => {
+                common.quiet = true;
+            }
+            Arg::Short('v') | Arg::Long("verbose") => {
+                common.verbose = true;
+            }
+            Arg::Short('o') if help.short_output => {
+                common.output = Some(parser.value()?.string()?);
+            }
+            Arg::Long("output") => {
+                common.output = Some(parser.value()?.string()?);
+            }
+            Arg::Long("limit") => {
+                let limit = parser.value()?.parse()?;
+                if limit == 0 {
+                    return Err("--limit must be greater than 0".into());
+                }
+                common.limit = Some(limit);
+            }
+            Arg::Long("input-list") => {
+                let path = parser.value()?.string()?;
+                common.files.extend(read_input_list(Path::new(&path))?);
+            }
+            Arg::Long("threads") if help.threads => {
+                common.threads = parser.value()?.parse()?;
+            }
+            Arg::Long("threads") => {
+                return Err(format!("{} works on one thread, --threads can't be used with it", help.name).into());
+            }
+            Arg::Long("hexdump-on-error") => {
+                common.hexdump_on_error = true;
+            }
+            Arg::Long("error-format") => {
+                common.error_format = parser.value()?.parse()?;
+            }
+            Arg::Long("config") => {
+                //already loaded by parse
+                parser.value()?;
+            }
+            Arg::Value(val) => {
+                common.files.push(val.string()?);
+            }
+            arg => tool_arg(arg, &mut parser)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn help(short_output: bool) -> Help<'static> {
+        Help {
+            name: "test",
+            version: "0",
+            usage: "",
+            description: &[],
+            options: &[],
+            notes: &[],
+            short_output,
+            threads: false,
+        }
+    }
+
+    fn parse_common(args: &[&str], short_output: bool) -> Result<CommonArgs, lexopt::Error> {
+        parse(args.iter().copied(), &help(short_output), |arg, _| Err(arg.unexpected()))
+    }
+
+    #[test]
+    fn short_output_is_opt_in() {
+        assert_eq!(parse_common(&["-o", "out", "a"], true).unwrap().output.as_deref(), Some("out"));
+        assert!(parse_common(&["-o", "out", "a"], false).is_err());
+        assert_eq!(parse_common(&["--output", "out", "a"], false).unwrap().output.as_deref(), Some("out"));
+    }
+
+    #[test]
+    fn threads_is_rejected_by_tools_without_them() {
+        let error = parse_common(&["--threads", "2", "a"], true).unwrap_err().to_string();
+        assert!(error.contains("--threads can't be used"), "{}", error);
+    }
+
+    #[test]
+    fn config_input_list_is_kept() {
+        let dir = std::env::temp_dir().join(format!("common-args-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("list.txt");
+        std::fs::write(&list, "# inputs\nfrom_list.gim\n").unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(&config, format!("input-list = {:?}\n", list.to_str().unwrap())).unwrap();
+
+        let common = parse_common(&["--config", config.to_str().unwrap(), "given.gim"], true).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(common.files, ["from_list.gim", "given.gim"]);
+    }
+}
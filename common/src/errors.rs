@@ -0,0 +1,82 @@
+//! Error output on stderr, either as the usual text lines or as one JSON object per line for programs that
+//! wrap the tools and want to show the errors themselves (`--error-format json`).
+
+use std::fmt;
+
+/// How errors are written to stderr, from `--error-format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("unknown error format '{}', expected text or json", s)),
+        }
+    }
+}
+
+/// The details of an error for the JSON output.
+pub struct ErrorReport<'a> {
+    /// The input file, or file within an archive, the error is about.
+    pub file: &'a str,
+    /// A short name for the kind of failure, e.g. `io` or `decode`, that stays the same whatever the message.
+    pub kind: &'a str,
+    pub message: &'a str,
+    /// The offset in the input where the failing data starts, when there is one.
+    pub offset: Option<u64>,
+}
+
+impl ErrorFormat {
+    /// Prints an error to stderr, `text` in text mode or `report` as a single line of JSON.
+    pub fn print(&self, text: fmt::Arguments, report: ErrorReport) {
+        match self {
+            ErrorFormat::Text => eprintln!("{}", text),
+            ErrorFormat::Json => {
+                let mut value = serde_json::json!({
+                    "file": report.file,
+                    "kind": report.kind,
+                    "message": report.message,
+                });
+                if let Some(offset) = report.offset {
+                    value["offset"] = offset.into();
+                }
+                eprintln!("{}", value);
+            }
+        }
+    }
+}
+
+/// Formats the bytes of `data` around `offset` as a hexdump of up to 8 lines of 16 bytes, with the line holding
+/// `offset` marked. `base` is added to the printed addresses, for data that is not the start of the file.
+pub fn hexdump(data: &[u8], offset: usize, base: u64) -> String {
+    const LINE: usize = 16;
+    const LINES: usize = 8;
+    let offset = offset.min(data.len().saturating_sub(1));
+    let start = (offset / LINE).saturating_sub(LINES / 2) * LINE;
+    let end = (start + LINE * LINES).min(data.len());
+
+    let mut out = String::new();
+    for line_start in (start..end).step_by(LINE) {
+        let line = &data[line_start..(line_start + LINE).min(end)];
+        let marker = if (line_start..line_start + LINE).contains(&offset) {
+            '>'
+        } else {
+            ' '
+        };
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("{} {:08X}  {:<47}  {}\n", marker, base + line_start as u64, hex.join(" "), ascii));
+    }
+    out
+}
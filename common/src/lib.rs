@@ -0,0 +1,12 @@
+pub mod args;
+pub mod errors;
+
+//macro to println based on a flag, that takes the flag as first arg and the rest as normal println args
+#[macro_export]
+macro_rules! vprintln {
+    ($verbose:expr, $($arg:tt)*) => {
+        if $verbose {
+            println!($($arg)*);
+        }
+    };
+}
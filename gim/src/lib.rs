@@ -0,0 +1,1278 @@
+//! Reading of PSP GIM image files.
+//!
+//! A GIM file is a header followed by a tree of chunks: a root chunk holding one or more picture chunks, each
+//! with an image chunk and, for indexed formats, a palette chunk. [`load_gim_image`] reads the first picture
+//! of a file borrowing from the buffer, [`load_gim_image_owned`] copies it out for callers that don't want to
+//! keep the buffer around.
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+use core::mem;
+use std::borrow::Cow;
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod, Debug)]
+struct GimHeader {
+    signature: u32,
+    version: u32,
+    style: u32,
+    option: u32,
+}
+
+const GIM_FORMAT_SIGNATURE: u32 = 0x2e47494d; /* '.GIM' */
+const GIM_FORMAT_VERSION: u32 = 0x312e3030; /* '1.00' */
+const GIM_FORMAT_STYLE_PSP: u32 = 0x00505350; /* 'PSP'  */
+const GIM_FORMAT_STYLE_PS3: u32 = 0x00505333; /* 'PS3'  */
+
+/// Whether the GIM in `buffer` is one of the big endian variants written by PS3 tooling, which start with the
+/// signature in byte order ('.GIM' rather than 'MIG.'). Their chunk and image header fields are big endian,
+/// the pixel data is the same.
+pub fn is_big_endian(buffer: &[u8]) -> bool {
+    buffer.starts_with(b".GIM")
+}
+
+/// Whether `buffer` starts with a valid GIM file header, in either byte order. Only the first 16 bytes are read.
+pub fn is_gim(buffer: &[u8]) -> bool {
+    gim_picture_check_file_header(buffer).is_ok()
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
+struct GimChunk {
+    chunk_type: u16,
+    unused: u16,
+    next_offs: u32,  //relative
+    child_offs: u32, //relative
+    data_offs: u32,  //relative
+}
+
+impl GimChunk {
+    fn kind(&self) -> GimChunkKind {
+        GimChunkKind::from_u16(self.chunk_type)
+    }
+
+    fn swap_bytes(&mut self) {
+        self.chunk_type = self.chunk_type.swap_bytes();
+        self.next_offs = self.next_offs.swap_bytes();
+        self.child_offs = self.child_offs.swap_bytes();
+        self.data_offs = self.data_offs.swap_bytes();
+    }
+}
+
+/// The chunk types found in a GIM file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GimChunkKind {
+    Root,
+    File,
+    Picture,
+    Image,
+    Palette,
+    Sequence,
+    FileInfo,
+    Unknown(u16),
+}
+
+impl GimChunkKind {
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            0x0001 => GimChunkKind::Root,
+            0x0002 => GimChunkKind::File,
+            0x0003 => GimChunkKind::Picture,
+            0x0004 => GimChunkKind::Image,
+            0x0005 => GimChunkKind::Palette,
+            0x0006 => GimChunkKind::Sequence,
+            0x00ff => GimChunkKind::FileInfo,
+            _ => GimChunkKind::Unknown(value),
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            GimChunkKind::Root => 0x0001,
+            GimChunkKind::File => 0x0002,
+            GimChunkKind::Picture => 0x0003,
+            GimChunkKind::Image => 0x0004,
+            GimChunkKind::Palette => 0x0005,
+            GimChunkKind::Sequence => 0x0006,
+            GimChunkKind::FileInfo => 0x00ff,
+            GimChunkKind::Unknown(value) => value,
+        }
+    }
+}
+
+impl std::fmt::Display for GimChunkKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GimChunkKind::Root => write!(f, "Root"),
+            GimChunkKind::File => write!(f, "File"),
+            GimChunkKind::Picture => write!(f, "Picture"),
+            GimChunkKind::Image => write!(f, "Image"),
+            GimChunkKind::Palette => write!(f, "Palette"),
+            GimChunkKind::Sequence => write!(f, "Sequence"),
+            GimChunkKind::FileInfo => write!(f, "FileInfo"),
+            GimChunkKind::Unknown(value) => write!(f, "Unknown(0x{:04X})", value),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+pub struct GimImageHeader {
+    pub header_size: u16,
+    pub reference: u16,
+    pub format: u16,
+    pub order: u16,
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u16,
+    pub pitch_align: u16,
+    pub height_align: u16,
+    pub dim_count: u16,
+    pub reserved: u16,
+    pub reserved2: u16,
+    pub offsets: u32,
+    pub images: u32,
+    pub total: u32,
+    pub plane_mask: u32,
+    /// How the mipmap levels are stored, see [`GIM_LEVEL_MIPMAP`].
+    pub level_type: u16,
+    pub level_count: u16,
+    /// How the frames are stored, see [`GIM_FRAME_SEQUENCE`].
+    pub frame_type: u16,
+    pub frame_count: u16,
+}
+
+/// The `level_type` of images whose levels are mipmaps, each half the size of the one before it and found
+/// through the offsets table. This is the only level layout that is understood, 0 is taken to mean the same.
+pub const GIM_LEVEL_MIPMAP: u16 = 1;
+
+/// The `frame_type` of images whose frames are a sequence of same sized images found through the offsets
+/// table. This is the only frame layout that is understood, 0 is taken to mean the same.
+pub const GIM_FRAME_SEQUENCE: u16 = 3;
+
+impl GimImageHeader {
+    /// A header for a single `width` x `height` image or palette in `format`, aligned to a pitch of 16 and a
+    /// height of 8 like the files GimConv writes. The offsets and sizes are filled in by [`write_gim`].
+    pub fn new(format: ImageFormat, order: ImageOrder, width: u16, height: u16) -> Self {
+        GimImageHeader {
+            header_size: mem::size_of::<GimImageHeader>() as u16,
+            reference: 0,
+            format: format as u16,
+            order: order as u16,
+            width,
+            height,
+            bpp: format.bits_per_pixel(),
+            pitch_align: 16,
+            height_align: 8,
+            dim_count: 2,
+            reserved: 0,
+            reserved2: 0,
+            offsets: 0,
+            images: 0,
+            total: 0,
+            plane_mask: 0,
+            level_type: GIM_LEVEL_MIPMAP,
+            level_count: 1,
+            frame_type: GIM_FRAME_SEQUENCE,
+            frame_count: 1,
+        }
+    }
+
+    fn swap_bytes(&mut self) {
+        for field in [
+            &mut self.header_size,
+            &mut self.reference,
+            &mut self.format,
+            &mut self.order,
+            &mut self.width,
+            &mut self.height,
+            &mut self.bpp,
+            &mut self.pitch_align,
+            &mut self.height_align,
+            &mut self.dim_count,
+            &mut self.reserved,
+            &mut self.reserved2,
+            &mut self.level_type,
+            &mut self.level_count,
+            &mut self.frame_type,
+            &mut self.frame_count,
+        ] {
+            *field = field.swap_bytes();
+        }
+        for field in [&mut self.offsets, &mut self.images, &mut self.total, &mut self.plane_mask] {
+            *field = field.swap_bytes();
+        }
+    }
+
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        self.format.try_into().ok()
+    }
+
+    pub fn image_order(&self) -> Option<ImageOrder> {
+        self.order.try_into().ok()
+    }
+
+    /// The single plane selected by `plane_mask`, if it selects exactly one. Bits 0 to 3 of the mask select
+    /// the red, green, blue and alpha planes, and a mask of 0 means every plane. An image of a single plane
+    /// stores one 8 bit sample per pixel, laid out like INDEX8 data.
+    pub fn single_plane(&self) -> Option<Plane> {
+        match self.plane_mask {
+            0x1 => Some(Plane::Red),
+            0x2 => Some(Plane::Green),
+            0x4 => Some(Plane::Blue),
+            0x8 => Some(Plane::Alpha),
+            _ => None,
+        }
+    }
+}
+
+/// One channel of an image, see [`GimImageHeader::single_plane`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Plane {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    RGBA5650 = 0,
+    RGBA5551 = 1,
+    RGBA4444 = 2,
+    RGBA8888 = 3,
+    INDEX4 = 4,
+    INDEX8 = 5,
+    INDEX16 = 6,
+    INDEX32 = 7,
+    DXT1 = 8,
+    DXT3 = 9,
+    DXT5 = 10,
+    DXT1EXT = 264,
+    DXT3EXT = 265,
+    DXT5EXT = 266,
+}
+
+impl ImageFormat {
+    /// Every format a GIM image or palette can declare.
+    pub const ALL: [ImageFormat; 14] = [
+        ImageFormat::RGBA5650,
+        ImageFormat::RGBA5551,
+        ImageFormat::RGBA4444,
+        ImageFormat::RGBA8888,
+        ImageFormat::INDEX4,
+        ImageFormat::INDEX8,
+        ImageFormat::INDEX16,
+        ImageFormat::INDEX32,
+        ImageFormat::DXT1,
+        ImageFormat::DXT3,
+        ImageFormat::DXT5,
+        ImageFormat::DXT1EXT,
+        ImageFormat::DXT3EXT,
+        ImageFormat::DXT5EXT,
+    ];
+
+    /// The number of bits per pixel the format stores, which the `bpp` field of the header should match.
+    /// Compressed formats give the average over a 4x4 block.
+    pub fn bits_per_pixel(&self) -> u16 {
+        match self {
+            ImageFormat::RGBA5650 | ImageFormat::RGBA5551 | ImageFormat::RGBA4444 => 16,
+            ImageFormat::RGBA8888 => 32,
+            ImageFormat::INDEX4 => 4,
+            ImageFormat::INDEX8 => 8,
+            ImageFormat::INDEX16 => 16,
+            ImageFormat::INDEX32 => 32,
+            ImageFormat::DXT1 | ImageFormat::DXT1EXT => 4,
+            ImageFormat::DXT3 | ImageFormat::DXT3EXT | ImageFormat::DXT5 | ImageFormat::DXT5EXT => 8,
+        }
+    }
+
+    /// The indexed format with the given bits per pixel, if there is one.
+    pub fn indexed_with_bpp(bpp: u16) -> Option<ImageFormat> {
+        match bpp {
+            4 => Some(ImageFormat::INDEX4),
+            8 => Some(ImageFormat::INDEX8),
+            16 => Some(ImageFormat::INDEX16),
+            32 => Some(ImageFormat::INDEX32),
+            _ => None,
+        }
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        matches!(self, ImageFormat::INDEX4 | ImageFormat::INDEX8 | ImageFormat::INDEX16 | ImageFormat::INDEX32)
+    }
+
+    /// The size in bytes of one 4x4 block for the DXT formats, `None` for the uncompressed ones.
+    pub fn dxt_block_size(&self) -> Option<usize> {
+        match self {
+            ImageFormat::DXT1 | ImageFormat::DXT1EXT => Some(8),
+            ImageFormat::DXT3 | ImageFormat::DXT3EXT | ImageFormat::DXT5 | ImageFormat::DXT5EXT => Some(16),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u16> for ImageFormat {
+    type Error = &'static str;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ImageFormat::RGBA5650),
+            1 => Ok(ImageFormat::RGBA5551),
+            2 => Ok(ImageFormat::RGBA4444),
+            3 => Ok(ImageFormat::RGBA8888),
+            4 => Ok(ImageFormat::INDEX4),
+            5 => Ok(ImageFormat::INDEX8),
+            6 => Ok(ImageFormat::INDEX16),
+            7 => Ok(ImageFormat::INDEX32),
+            8 => Ok(ImageFormat::DXT1),
+            9 => Ok(ImageFormat::DXT3),
+            10 => Ok(ImageFormat::DXT5),
+            264 => Ok(ImageFormat::DXT1EXT),
+            265 => Ok(ImageFormat::DXT3EXT),
+            266 => Ok(ImageFormat::DXT5EXT),
+            _ => Err("Invalid enum value"),
+        }
+    }
+}
+
+impl std::str::FromStr for ImageFormat {
+    type Err = String;
+
+    /// Parses a format from its name as shown by `Display`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImageFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown image format '{}'", s))
+    }
+}
+
+impl std::fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ImageFormat::RGBA5650 => "RGBA5650",
+            ImageFormat::RGBA5551 => "RGBA5551",
+            ImageFormat::RGBA4444 => "RGBA4444",
+            ImageFormat::RGBA8888 => "RGBA8888",
+            ImageFormat::INDEX4 => "INDEX4",
+            ImageFormat::INDEX8 => "INDEX8",
+            ImageFormat::INDEX16 => "INDEX16",
+            ImageFormat::INDEX32 => "INDEX32",
+            ImageFormat::DXT1 => "DXT1",
+            ImageFormat::DXT3 => "DXT3",
+            ImageFormat::DXT5 => "DXT5",
+            ImageFormat::DXT1EXT => "DXT1EXT",
+            ImageFormat::DXT3EXT => "DXT3EXT",
+            ImageFormat::DXT5EXT => "DXT5EXT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageOrder {
+    Normal = 0,
+    PSPImage = 1,
+}
+
+impl TryFrom<u16> for ImageOrder {
+    type Error = &'static str;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ImageOrder::Normal),
+            1 => Ok(ImageOrder::PSPImage),
+            _ => Err("Invalid enum value"),
+        }
+    }
+}
+
+impl std::fmt::Display for ImageOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ImageOrder::Normal => "Normal",
+            ImageOrder::PSPImage => "PSPImage",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn gim_picture_check_file_header(buffer: &[u8]) -> Result<()> {
+    let header_bytes = buffer
+        .get(0..mem::size_of::<GimHeader>())
+        .context("Buffer is too small for a GIM header")?;
+    //read unaligned so the size of a GIM found at any offset can be worked out without copying it
+    let mut header: GimHeader = bytemuck::pod_read_unaligned(header_bytes);
+    let big_endian = is_big_endian(buffer);
+    if big_endian {
+        header.signature = header.signature.swap_bytes();
+        header.version = header.version.swap_bytes();
+        header.style = header.style.swap_bytes();
+    }
+
+    if header.signature != GIM_FORMAT_SIGNATURE {
+        return Err(offset_error(0, "Invalid GIM signature".to_string()));
+    }
+    if header.version != GIM_FORMAT_VERSION {
+        return Err(offset_error(4, "Unsupported GIM version".to_string()));
+    }
+    if header.style != GIM_FORMAT_STYLE_PSP && !(big_endian && header.style == GIM_FORMAT_STYLE_PS3) {
+        return Err(offset_error(8, "Unsupported GIM style".to_string()));
+    }
+
+    Ok(())
+}
+
+fn gim_picture_get_chunk_header(bytes: &[u8], start: usize) -> Result<GimChunk> {
+    let end = start + mem::size_of::<GimChunk>();
+    let chunk_bytes = bytes
+        .get(start..end)
+        .ok_or_else(|| offset_error(start, format!("Chunk header at offset 0x{:X} is beyond the end of the buffer", start)))?;
+    let mut chunk: GimChunk = bytemuck::pod_read_unaligned(chunk_bytes);
+    if is_big_endian(bytes) {
+        chunk.swap_bytes();
+    }
+    Ok(chunk)
+}
+
+/// How far past the file header to look for the root chunk when it is not directly after it.
+const GIM_ROOT_SEARCH_WINDOW: usize = 0x100;
+
+/// Checks that the chunk at `offset` looks like a root chunk: a Root or File chunk whose children and
+/// size fit inside the buffer.
+fn gim_is_root_chunk(buffer: &[u8], offset: usize) -> Option<GimChunk> {
+    let chunk = gim_picture_get_chunk_header(buffer, offset).ok()?;
+    let header_size = mem::size_of::<GimChunk>() as u32;
+    let valid = matches!(chunk.kind(), GimChunkKind::Root | GimChunkKind::File)
+        && chunk.next_offs >= header_size
+        && chunk.child_offs >= header_size
+        && chunk.child_offs <= chunk.next_offs
+        && offset + chunk.next_offs as usize <= buffer.len();
+    valid.then_some(chunk)
+}
+
+/// Finds the root chunk, which normally follows the file header directly. Some files have padding or a
+/// longer header, so if there is no valid chunk there the next few hundred bytes are searched for one.
+fn gim_find_root_chunk(buffer: &[u8]) -> Result<(GimChunk, usize)> {
+    let expected = mem::size_of::<GimHeader>();
+    let search_end = (expected + GIM_ROOT_SEARCH_WINDOW).min(buffer.len());
+    (expected..search_end)
+        .step_by(4)
+        .find_map(|offset| gim_is_root_chunk(buffer, offset).map(|chunk| (chunk, offset)))
+        .ok_or_else(|| {
+            offset_error(
+                expected,
+                format!(
+                    "No valid root chunk at offset 0x{:X} or in the 0x{:X} bytes after it",
+                    expected, GIM_ROOT_SEARCH_WINDOW
+                ),
+            )
+        })
+}
+
+fn gim_get_child_chunk(
+    buffer: &[u8],
+    start_offset: usize,
+    parent_chunk: &GimChunk,
+    kind: GimChunkKind,
+) -> Result<Option<(GimChunk, usize)>> {
+    let (mut child_offs, chunk_end) = gim_children_range(start_offset, parent_chunk)?;
+    let mut found_chunk = None;
+    while child_offs < chunk_end {
+        //this needs to be relative
+        let child_chunk = gim_read_child_chunk(buffer, child_offs, chunk_end)?;
+        if child_chunk.kind() == kind {
+            found_chunk = Some((child_chunk, child_offs));
+        }
+        child_offs += child_chunk.next_offs as usize;
+    }
+    Ok(found_chunk)
+}
+
+/// The offset of the first child of the chunk at `offset` and the end of the chunk, which the children must fit
+/// in. The children start after the chunk's own header, so a chunk can never be its own child.
+fn gim_children_range(offset: usize, chunk: &GimChunk) -> Result<(usize, usize)> {
+    let header_size = mem::size_of::<GimChunk>();
+    let (child_offs, next_offs) = (chunk.child_offs as usize, chunk.next_offs as usize);
+    if child_offs < header_size || child_offs > next_offs {
+        return Err(offset_error(
+            offset,
+            format!(
+                "Chunk at offset 0x{:X} has children at 0x{:X}, outside its 0x{:X} bytes",
+                offset, child_offs, next_offs
+            ),
+        ));
+    }
+    Ok((offset + child_offs, offset + next_offs))
+}
+
+/// Reads the header of a child chunk at `offset` whose parent ends at `parent_end`. The chunk must be at least as
+/// big as its header, so walking the children always moves forward, and must end within its parent.
+fn gim_read_child_chunk(buffer: &[u8], offset: usize, parent_end: usize) -> Result<GimChunk> {
+    let chunk = gim_picture_get_chunk_header(buffer, offset).context("child chunk should be valid")?;
+    let size = chunk.next_offs as usize;
+    if size < mem::size_of::<GimChunk>() {
+        return Err(offset_error(offset, format!("Chunk at offset 0x{:X} has an invalid size of {}", offset, size)));
+    }
+    if offset + size > parent_end {
+        return Err(offset_error(
+            offset,
+            format!(
+                "Chunk at offset 0x{:X} ends at 0x{:X}, past the end of its parent at 0x{:X}",
+                offset,
+                offset + size,
+                parent_end
+            ),
+        ));
+    }
+    Ok(chunk)
+}
+
+/// Iterates over all child chunks of a parent, calling the callback for each child.
+/// The callback receives (GimChunk, offset) and can return a Result.
+/// If the callback returns an error, iteration stops and the error is returned.
+fn gim_process_child_chunks<F>(buffer: &[u8], start_offset: usize, parent_chunk: &GimChunk, mut callback: F) -> Result<()>
+where
+    F: FnMut(GimChunk, usize) -> Result<()>,
+{
+    let (mut child_offs, chunk_end) = gim_children_range(start_offset, parent_chunk)?;
+    while child_offs < chunk_end {
+        let child_chunk = gim_read_child_chunk(buffer, child_offs, chunk_end)?;
+        callback(child_chunk, child_offs)?;
+        child_offs += child_chunk.next_offs as usize;
+    }
+    Ok(())
+}
+
+/// Resolves an offset stored in an image or palette header to a position in the buffer.
+/// Offsets are normally forward from the header, but some authoring tools store negative
+/// (two's complement) values that point back before it, so the value is treated as signed.
+fn gim_resolve_offset(header_offset: usize, relative: u32) -> Result<usize> {
+    let absolute = header_offset as i64 + relative as i32 as i64;
+    usize::try_from(absolute).map_err(|_| {
+        offset_error(
+            header_offset,
+            format!(
+                "Offset {} from header at 0x{:X} points before the start of the buffer",
+                relative as i32, header_offset
+            ),
+        )
+    })
+}
+
+/// Checks that `start..end` is a valid range of the buffer that does not overlap the image header itself.
+fn gim_check_block_range(buffer: &[u8], header_offset: usize, start: usize, end: usize, what: &str) -> Result<()> {
+    if start > end {
+        return Err(offset_error(
+            header_offset,
+            format!(
+                "Invalid {} range in header at offset 0x{:X}: start 0x{:X} is after end 0x{:X}",
+                what, header_offset, start, end
+            ),
+        ));
+    }
+    if end > buffer.len() {
+        return Err(offset_error(
+            header_offset,
+            format!(
+                "The {} range 0x{:X}..0x{:X} in header at offset 0x{:X} is beyond the end of the buffer (length 0x{:X})",
+                what,
+                start,
+                end,
+                header_offset,
+                buffer.len()
+            ),
+        ));
+    }
+    let header_end = header_offset + mem::size_of::<GimImageHeader>();
+    if start < header_end && end > header_offset && start != end {
+        return Err(offset_error(
+            header_offset,
+            format!("The {} range 0x{:X}..0x{:X} overlaps the header at offset 0x{:X}", what, start, end, header_offset),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the header, offsets table and data of an image or palette chunk.
+/// The offsets table and the data region are located independently, so either may come first.
+fn gim_read_image_block<'a>(buffer: &'a [u8], chunk_offset: usize, chunk: &GimChunk) -> Result<ImageBlock<'a>> {
+    let header_offset = chunk_offset + chunk.data_offs as usize;
+    let header_end = header_offset + mem::size_of::<GimImageHeader>();
+    if header_end > buffer.len() {
+        return Err(offset_error(
+            header_offset,
+            format!(
+                "Image header at offset 0x{:X} is beyond the end of the buffer (length 0x{:X})",
+                header_offset,
+                buffer.len()
+            ),
+        ));
+    }
+    let mut header = *bytemuck::try_from_bytes::<GimImageHeader>(&buffer[header_offset..header_end])
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("Failed to read GIM image header")?;
+    let big_endian = is_big_endian(buffer);
+    if big_endian {
+        header.swap_bytes();
+    }
+
+    let offsets_size = (header.level_count as usize * header.frame_count as usize) * mem::size_of::<u32>();
+    let offsets_start = gim_resolve_offset(header_offset, header.offsets)?;
+    let offsets_end = offsets_start + offsets_size;
+    gim_check_block_range(buffer, header_offset, offsets_start, offsets_end, "offsets table")?;
+    let offsets_bytes = &buffer[offsets_start..offsets_end];
+    let offsets = if big_endian {
+        Cow::Owned(
+            offsets_bytes
+                .chunks_exact(4)
+                .map(|o| u32::from_be_bytes([o[0], o[1], o[2], o[3]]))
+                .collect(),
+        )
+    } else {
+        Cow::Borrowed(
+            bytemuck::try_cast_slice(offsets_bytes)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to read GIM image offsets")?,
+        )
+    };
+
+    let data_start = gim_resolve_offset(header_offset, header.images)?;
+    let data_end = gim_resolve_offset(header_offset, header.total)?;
+    gim_check_block_range(buffer, header_offset, data_start, data_end, "data")?;
+
+    Ok((header, offsets, &buffer[data_start..data_end]))
+}
+
+/// The header, offsets table and data of an image or palette chunk.
+type ImageBlock<'a> = (GimImageHeader, Cow<'a, [u32]>, &'a [u8]);
+
+/// A chunk found by [`walk_chunks`], with its offsets as stored in the file (relative to `offset`).
+#[derive(Clone, Copy, Debug)]
+pub struct GimChunkInfo {
+    pub kind: GimChunkKind,
+    pub offset: usize,
+    pub depth: usize,
+    pub next_offs: u32,
+    pub child_offs: u32,
+    pub data_offs: u32,
+}
+
+/// Walks the whole chunk tree of a GIM file depth first, starting at the root chunk, and calls the
+/// callback for every chunk found. Chunks whose `child_offs` is before their `next_offs` have children.
+/// If the callback returns an error, the walk stops and the error is returned.
+pub fn walk_chunks<F>(buffer: &[u8], mut callback: F) -> Result<()>
+where
+    F: FnMut(&GimChunkInfo) -> Result<()>,
+{
+    gim_picture_check_file_header(buffer)?;
+    let (root_chunk, start_offset) = gim_find_root_chunk(buffer)?;
+    gim_walk_chunk(buffer, start_offset, root_chunk, 0, &mut callback)
+}
+
+/// How deeply [`walk_chunks`] follows nested chunks. Real files only go a few levels deep, this stops a file
+/// of chunks nested inside each other from overflowing the stack.
+const GIM_MAX_DEPTH: usize = 16;
+
+/// Reports the chunk at `offset` and then its children, `depth` being how deeply the chunk is nested. Stops at
+/// the first error, from a malformed chunk or from the callback.
+fn gim_walk_chunk<F>(buffer: &[u8], offset: usize, chunk: GimChunk, depth: usize, callback: &mut F) -> Result<()>
+where
+    F: FnMut(&GimChunkInfo) -> Result<()>,
+{
+    callback(&GimChunkInfo {
+        kind: chunk.kind(),
+        offset,
+        depth,
+        next_offs: chunk.next_offs,
+        child_offs: chunk.child_offs,
+        data_offs: chunk.data_offs,
+    })?;
+
+    let (mut child_offs, chunk_end) = gim_children_range(offset, &chunk)?;
+    if child_offs < chunk_end && depth + 1 > GIM_MAX_DEPTH {
+        return Err(offset_error(
+            child_offs,
+            format!("Chunks are nested more than {} deep at offset 0x{:X}", GIM_MAX_DEPTH, child_offs),
+        ));
+    }
+    while child_offs < chunk_end {
+        let child_chunk = gim_read_child_chunk(buffer, child_offs, chunk_end)?;
+        gim_walk_chunk(buffer, child_offs, child_chunk, depth + 1, callback)?;
+        child_offs += child_chunk.next_offs as usize;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct GimPicture<'a> {
+    pub image_header: GimImageHeader,
+    pub image_offsets: Cow<'a, [u32]>,
+    pub image_data: &'a [u8],
+    pub palette_header: Option<GimImageHeader>,
+    pub palette_offsets: Option<Cow<'a, [u32]>>,
+    pub palette_data: Option<&'a [u8]>,
+}
+
+impl<'a> GimPicture<'a> {
+    /// Copies the headers, offsets and data out of the file buffer, see [`OwnedGimPicture`].
+    pub fn into_owned(self) -> OwnedGimPicture {
+        OwnedGimPicture {
+            image_header: self.image_header,
+            image_offsets: self.image_offsets.into_owned(),
+            image_data: self.image_data.to_vec(),
+            palette_header: self.palette_header,
+            palette_offsets: self.palette_offsets.map(Cow::into_owned),
+            palette_data: self.palette_data.map(<[u8]>::to_vec),
+        }
+    }
+
+    /// The real width and height of the image, as declared in the image header.
+    pub fn dimensions(&self) -> (u16, u16) {
+        (self.image_header.width, self.image_header.height)
+    }
+
+    /// The number of entries in the palette, as declared by its width and height but no more than the palette
+    /// data holds. `None` when there is no palette.
+    pub fn palette_entry_count(&self) -> Option<usize> {
+        let header = self.palette_header.as_ref()?;
+        let declared = header.width as usize * header.height as usize;
+        let bits = header.image_format().map_or(32, |format| format.bits_per_pixel()) as usize;
+        let stored = self.palette_data.map_or(0, |data| data.len() * 8 / bits);
+        Some(declared.min(stored))
+    }
+
+    /// The width and height of the image data, which is padded out to `pitch_align` and `height_align`.
+    /// An alignment of 0 is treated as no alignment.
+    pub fn aligned_dimensions(&self) -> (usize, usize) {
+        self.aligned_level_dimensions(0)
+    }
+
+    /// The real width and height of a mipmap level, each level is half the size of the one before it.
+    pub fn level_dimensions(&self, level: usize) -> (u16, u16) {
+        let (width, height) = self.dimensions();
+        let shift = level.min(15) as u32;
+        ((width >> shift).max(1), (height >> shift).max(1))
+    }
+
+    /// The width and height of the data of a mipmap level, see [`GimPicture::aligned_dimensions`].
+    /// The height of a 1D texture is not padded.
+    pub fn aligned_level_dimensions(&self, level: usize) -> (usize, usize) {
+        let (width, height) = self.level_dimensions(level);
+        let pitch_align = self.image_header.pitch_align.max(1) as usize;
+        let height_align = if self.is_one_dimensional() {
+            1
+        } else {
+            self.image_header.height_align.max(1) as usize
+        };
+        (
+            (width as usize).div_ceil(pitch_align) * pitch_align,
+            (height as usize).div_ceil(height_align) * height_align,
+        )
+    }
+
+    /// Whether the image is a 1D texture, a single row such as a lookup table. Either `dim_count` says so or the
+    /// height is 1. A 1D texture is always stored linearly, whatever the order in its header.
+    pub fn is_one_dimensional(&self) -> bool {
+        self.image_header.dim_count == 1 || self.image_header.height == 1
+    }
+
+    /// Checks that the levels and frames are stored in a layout [`GimPicture::image_index`] understands, with an
+    /// offsets table entry for each of them. This only matters when there is more than one image.
+    pub fn check_layout(&self) -> Result<()> {
+        let header = &self.image_header;
+        if header.level_count > 1 && !matches!(header.level_type, 0 | GIM_LEVEL_MIPMAP) {
+            anyhow::bail!(
+                "Unsupported level type {} for {} levels, only mipmaps (1) are supported",
+                header.level_type,
+                header.level_count
+            );
+        }
+        if header.frame_count > 1 && !matches!(header.frame_type, 0 | GIM_FRAME_SEQUENCE) {
+            anyhow::bail!(
+                "Unsupported frame type {} for {} frames, only sequences (3) are supported",
+                header.frame_type,
+                header.frame_count
+            );
+        }
+        let images = header.level_count.max(1) as usize * header.frame_count.max(1) as usize;
+        if self.image_offsets.len() != images {
+            anyhow::bail!(
+                "The offsets table has {} entries but {} levels of {} frames need {}",
+                self.image_offsets.len(),
+                header.level_count,
+                header.frame_count,
+                images
+            );
+        }
+        Ok(())
+    }
+
+    /// The position in the offsets table of a frame of a mipmap level. The table is level major, the level
+    /// is the outer index: every frame of level 0 comes first, then every frame of level 1 and so on.
+    pub fn image_index(&self, level: usize, frame: usize) -> usize {
+        level * self.image_header.frame_count.max(1) as usize + frame
+    }
+
+    /// Returns the data of the image at `index` in the offsets table, up to the start of the image that
+    /// follows it (or the end of the data). Offsets are relative to the header, like `images`.
+    pub fn image_data_at(&self, index: usize) -> Result<&'a [u8]> {
+        let offset = *self
+            .image_offsets
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("Image {} is beyond the offsets table ({} entries)", index, self.image_offsets.len()))?;
+        let start = offset.wrapping_sub(self.image_header.images) as usize;
+        if offset < self.image_header.images || start >= self.image_data.len() {
+            anyhow::bail!("Offset 0x{:X} of image {} is outside the image data", offset, index);
+        }
+        let end = self
+            .image_offsets
+            .iter()
+            .filter(|&&o| o > offset)
+            .min()
+            .map_or(self.image_data.len(), |&o| (o - self.image_header.images) as usize)
+            .min(self.image_data.len());
+        Ok(&self.image_data[start..end])
+    }
+}
+
+/// A [`GimPicture`] that owns its data rather than borrowing it from the file buffer.
+#[derive(Clone, Debug)]
+pub struct OwnedGimPicture {
+    pub image_header: GimImageHeader,
+    pub image_offsets: Vec<u32>,
+    pub image_data: Vec<u8>,
+    pub palette_header: Option<GimImageHeader>,
+    pub palette_offsets: Option<Vec<u32>>,
+    pub palette_data: Option<Vec<u8>>,
+}
+
+impl OwnedGimPicture {
+    /// Borrows the picture as a [`GimPicture`], which has the methods to locate and size its images.
+    pub fn as_picture(&self) -> GimPicture<'_> {
+        GimPicture {
+            image_header: self.image_header,
+            image_offsets: Cow::Borrowed(&self.image_offsets),
+            image_data: &self.image_data,
+            palette_header: self.palette_header,
+            palette_offsets: self.palette_offsets.as_deref().map(Cow::Borrowed),
+            palette_data: self.palette_data.as_deref(),
+        }
+    }
+}
+
+/// Returns the size in bytes of the GIM file at the start of the buffer (up to the end of the root chunk),
+/// which lets files that are concatenated together be split apart. Unlike loading the image, the buffer doesn't
+/// need to be aligned.
+pub fn file_size(buffer: &[u8]) -> Result<usize> {
+    gim_picture_check_file_header(buffer)?;
+
+    //the root chunk is only found if it fits in the buffer
+    let (root_chunk, start_offset) = gim_find_root_chunk(buffer)?;
+    Ok(start_offset + root_chunk.next_offs as usize)
+}
+
+/// Converts PSP tiled image data to linear rows. `pitch` is the width of a row in bytes and `tile_pitch` the
+/// width of a tile in bytes (16 for every PSP format), and both dimensions must be whole numbers of tiles.
+pub fn unswizzle(data: &[u8], pitch: usize, height: usize, tile_pitch: usize, tile_height: usize) -> Result<Vec<u8>> {
+    gim_retile(data, pitch, height, tile_pitch, tile_height, false)
+}
+
+/// Converts linear image data to PSP tiles, the inverse of [`unswizzle`].
+pub fn swizzle(data: &[u8], pitch: usize, height: usize, tile_pitch: usize, tile_height: usize) -> Result<Vec<u8>> {
+    gim_retile(data, pitch, height, tile_pitch, tile_height, true)
+}
+
+fn gim_retile(data: &[u8], pitch: usize, height: usize, tile_pitch: usize, tile_height: usize, to_tiles: bool) -> Result<Vec<u8>> {
+    if tile_pitch == 0 || tile_height == 0 || !pitch.is_multiple_of(tile_pitch) || !height.is_multiple_of(tile_height) {
+        anyhow::bail!("{} bytes x {} rows is not a whole number of {} x {} tiles", pitch, height, tile_pitch, tile_height);
+    }
+    let size = pitch * height;
+    if data.len() < size {
+        anyhow::bail!("Image data is {} bytes but {} bytes x {} rows needs {}", data.len(), pitch, height, size);
+    }
+
+    let tiles_x = pitch / tile_pitch;
+    let mut out = vec![0u8; size];
+    //tiles are stored one after another, each as tile_height rows of tile_pitch bytes
+    for (tile_index, tile_offset) in (0..size).step_by(tile_pitch * tile_height).enumerate() {
+        let (tx, ty) = (tile_index % tiles_x, tile_index / tiles_x);
+        for y in 0..tile_height {
+            let tiled = tile_offset + y * tile_pitch;
+            let linear = (ty * tile_height + y) * pitch + tx * tile_pitch;
+            let (src, dst) = if to_tiles { (linear, tiled) } else { (tiled, linear) };
+            out[dst..dst + tile_pitch].copy_from_slice(&data[src..src + tile_pitch]);
+        }
+    }
+    Ok(out)
+}
+
+pub fn load_gim_image<'a>(buffer: &'a [u8]) -> Result<GimPicture<'a>> {
+    gim_picture_check_file_header(buffer)?;
+
+    let (root_chunk, start_offset) = gim_find_root_chunk(buffer)?;
+
+    //look for a child chunk that is a picture
+    match gim_get_child_chunk(buffer, start_offset, &root_chunk, GimChunkKind::Picture)? {
+        Some((chunk, offset)) => gim_load_picture(buffer, offset, &chunk),
+        None => anyhow::bail!("Picture chunk not found"),
+    }
+}
+
+/// Loads the first picture of the file like [`load_gim_image`], but copies it out so the buffer can be dropped.
+pub fn load_gim_image_owned(buffer: &[u8]) -> Result<OwnedGimPicture> {
+    load_gim_image(buffer).map(GimPicture::into_owned)
+}
+
+/// Loads every picture in the file in order. Pictures whose image chunk has no pixel data are skipped,
+/// any other failure is returned for that picture.
+pub fn iter_pictures<'a>(buffer: &'a [u8]) -> Result<impl Iterator<Item = Result<GimPicture<'a>>> + 'a> {
+    gim_picture_check_file_header(buffer)?;
+
+    let (root_chunk, start_offset) = gim_find_root_chunk(buffer)?;
+
+    let mut pictures = Vec::new();
+    gim_process_child_chunks(buffer, start_offset, &root_chunk, |child_chunk, child_offset| {
+        if child_chunk.kind() == GimChunkKind::Picture {
+            pictures.push((child_chunk, child_offset));
+        }
+        Ok(())
+    })?;
+
+    Ok(pictures
+        .into_iter()
+        .filter_map(move |(chunk, offset)| match gim_load_picture(buffer, offset, &chunk) {
+            Err(e) if e.downcast_ref::<EmptyImageError>().is_some() => None,
+            result => Some(result),
+        }))
+}
+
+/// Returns the image block that `header` refers to. The reference is the position, counting from 1, of the
+/// image chunk among every image chunk in the file in file order. The referenced image must have the same
+/// format and size and can't be a reference itself.
+fn gim_resolve_image_reference<'a>(buffer: &'a [u8], header: &GimImageHeader) -> Result<ImageBlock<'a>> {
+    let (root_chunk, start_offset) = gim_find_root_chunk(buffer)?;
+    let mut images = Vec::new();
+    gim_process_child_chunks(buffer, start_offset, &root_chunk, |picture_chunk, picture_offset| {
+        if picture_chunk.kind() == GimChunkKind::Picture {
+            gim_process_child_chunks(buffer, picture_offset, &picture_chunk, |child_chunk, child_offset| {
+                if child_chunk.kind() == GimChunkKind::Image {
+                    images.push((child_chunk, child_offset));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?;
+
+    let Some((chunk, offset)) = images.get(header.reference as usize - 1) else {
+        anyhow::bail!("The file only has {} images", images.len());
+    };
+    let (target, offsets, data) = gim_read_image_block(buffer, *offset, chunk).context("Failed to read referenced GIM image")?;
+    if target.reference != 0 && target.images == target.total {
+        anyhow::bail!("Image {} is a reference itself", header.reference);
+    }
+    if (target.format, target.width, target.height) != (header.format, header.width, header.height) {
+        anyhow::bail!(
+            "Image {} is {} x {} in format {} but the referencing image is {} x {} in format {}",
+            header.reference,
+            target.width,
+            target.height,
+            target.format,
+            header.width,
+            header.height,
+            header.format
+        );
+    }
+    Ok((target, offsets, data))
+}
+
+/// An error about the data at a known offset in the buffer, so the bytes there can be shown with it.
+#[derive(Debug)]
+pub struct OffsetError {
+    pub offset: usize,
+    message: String,
+}
+
+impl std::fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for OffsetError {}
+
+fn offset_error(offset: usize, message: String) -> anyhow::Error {
+    OffsetError { offset, message }.into()
+}
+
+/// Returned when an image chunk is valid but its data region is empty.
+#[derive(Debug)]
+pub struct EmptyImageError {
+    pub header_offset: usize,
+}
+
+impl std::fmt::Display for EmptyImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Image chunk contains no pixel data (images == total at offset 0x{:X})", self.header_offset)
+    }
+}
+
+impl std::error::Error for EmptyImageError {}
+
+fn gim_load_picture<'a>(buffer: &'a [u8], offset: usize, chunk: &GimChunk) -> Result<GimPicture<'a>> {
+    let mut image_header = None;
+    let mut image_offsets = None;
+    let mut image_data = None;
+    let mut palette = None;
+    let mut nested_palette = None;
+    gim_process_child_chunks(buffer, offset, chunk, |child_chunk, child_offset| {
+        match child_chunk.kind() {
+            GimChunkKind::Image => {
+                let (header, offsets, data) =
+                    gim_read_image_block(buffer, child_offset, &child_chunk).context("Failed to read GIM image")?;
+                //an image without data of its own can share the data of the image its reference points to
+                let (header, offsets, data) = if data.is_empty() && header.reference != 0 {
+                    gim_resolve_image_reference(buffer, &header)
+                        .with_context(|| format!("Failed to resolve image reference {}", header.reference))?
+                } else {
+                    (header, offsets, data)
+                };
+                if data.is_empty() {
+                    return Err(EmptyImageError {
+                        header_offset: child_offset + child_chunk.data_offs as usize,
+                    }
+                    .into());
+                }
+                image_header = Some(header);
+                image_offsets = Some(offsets);
+                image_data = Some(data);
+
+                //some files have the palette inside the image chunk instead of next to it
+                let has_children =
+                    child_chunk.child_offs as usize >= mem::size_of::<GimChunk>() && child_chunk.child_offs < child_chunk.next_offs;
+                if has_children
+                    && let Some((palette_chunk, palette_offset)) =
+                        gim_get_child_chunk(buffer, child_offset, &child_chunk, GimChunkKind::Palette)?
+                {
+                    nested_palette =
+                        Some(gim_read_image_block(buffer, palette_offset, &palette_chunk).context("Failed to read GIM palette")?);
+                }
+            }
+            GimChunkKind::Palette => {
+                palette = Some(gim_read_image_block(buffer, child_offset, &child_chunk).context("Failed to read GIM palette")?);
+            }
+            _ => {
+                anyhow::bail!("Unsupported child chunk type: {}", child_chunk.kind());
+            }
+        }
+        Ok(())
+    })?;
+
+    //a palette next to the image wins over one inside it
+    let (palette_header, palette_offsets, palette_data) = match palette.or(nested_palette) {
+        Some((header, offsets, data)) => (Some(header), Some(offsets), Some(data)),
+        None => (None, None, None),
+    };
+    Ok(GimPicture {
+        image_header: image_header.ok_or_else(|| anyhow::anyhow!("Image header not found"))?,
+        image_offsets: image_offsets.ok_or_else(|| anyhow::anyhow!("Image offsets not found"))?,
+        image_data: image_data.ok_or_else(|| anyhow::anyhow!("Image data not found"))?,
+        palette_header,
+        palette_offsets,
+        palette_data,
+    })
+}
+
+/// Builds a little endian GIM file holding a single picture: the `image` header and data, and the `palette`
+/// header and entries for indexed formats. The data is written as given, already aligned and tiled to match
+/// the headers, and the offsets and sizes in the headers are filled in here.
+pub fn write_gim(image: (GimImageHeader, &[u8]), palette: Option<(GimImageHeader, &[u8])>) -> Vec<u8> {
+    let mut children = gim_image_chunk(GimChunkKind::Image, image.0, image.1);
+    if let Some((header, data)) = palette {
+        children.extend(gim_image_chunk(GimChunkKind::Palette, header, data));
+    }
+    let picture = gim_chunk(GimChunkKind::Picture, &[], &children);
+    let root = gim_chunk(GimChunkKind::File, &[], &picture);
+
+    let header = GimHeader {
+        signature: GIM_FORMAT_SIGNATURE,
+        version: GIM_FORMAT_VERSION,
+        style: GIM_FORMAT_STYLE_PSP,
+        option: 0,
+    };
+    let mut out = bytemuck::bytes_of(&header).to_vec();
+    out.extend(root);
+    out
+}
+
+/// An image or palette chunk with a single image: the header, an offsets table of one entry, and the data
+/// starting on the next 16 byte boundary. The chunk is padded to 16 bytes so the next one stays aligned.
+fn gim_image_chunk(kind: GimChunkKind, mut header: GimImageHeader, data: &[u8]) -> Vec<u8> {
+    let header_size = mem::size_of::<GimImageHeader>();
+    let images = (header_size + mem::size_of::<u32>()).next_multiple_of(16);
+    header.header_size = header_size as u16;
+    header.offsets = header_size as u32;
+    header.images = images as u32;
+    header.total = (images + data.len()) as u32;
+
+    let mut body = bytemuck::bytes_of(&header).to_vec();
+    body.extend((images as u32).to_le_bytes());
+    body.resize(images, 0);
+    body.extend(data);
+    body.resize(body.len().next_multiple_of(16), 0);
+    gim_chunk(kind, &body, &[])
+}
+
+/// A chunk header followed by its own data and then its children, with the offsets relative to the header.
+fn gim_chunk(kind: GimChunkKind, data: &[u8], children: &[u8]) -> Vec<u8> {
+    let header_size = mem::size_of::<GimChunk>();
+    let chunk = GimChunk {
+        chunk_type: kind.to_u16(),
+        unused: 0,
+        next_offs: (header_size + data.len() + children.len()) as u32,
+        child_offs: (header_size + data.len()) as u32,
+        data_offs: header_size as u32,
+    };
+    let mut out = bytemuck::bytes_of(&chunk).to_vec();
+    out.extend(data);
+    out.extend(children);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wraps the children of a picture chunk into a whole GIM file, as [`write_gim`] does.
+    fn gim_file(picture_children: &[u8]) -> Vec<u8> {
+        let picture = gim_chunk(GimChunkKind::Picture, &[], picture_children);
+        let root = gim_chunk(GimChunkKind::File, &[], &picture);
+        let header = GimHeader {
+            signature: GIM_FORMAT_SIGNATURE,
+            version: GIM_FORMAT_VERSION,
+            style: GIM_FORMAT_STYLE_PSP,
+            option: 0,
+        };
+        let mut out = bytemuck::bytes_of(&header).to_vec();
+        out.extend(root);
+        out
+    }
+
+    #[test]
+    fn finds_palette_nested_in_image_chunk() {
+        let indices = [1u8; 16 * 8];
+        let palette: Vec<u8> = (0..16 * 4).map(|i| i as u8).collect();
+        let image =
+            gim_image_chunk(GimChunkKind::Image, GimImageHeader::new(ImageFormat::INDEX8, ImageOrder::Normal, 16, 8), &indices);
+        let palette_chunk = gim_image_chunk(
+            GimChunkKind::Palette,
+            GimImageHeader::new(ImageFormat::RGBA8888, ImageOrder::Normal, 16, 1),
+            &palette,
+        );
+        //the image's own header and data, with the palette as its child rather than its sibling
+        let image_body = &image[mem::size_of::<GimChunk>()..];
+        let data = gim_file(&gim_chunk(GimChunkKind::Image, image_body, &palette_chunk));
+
+        let picture = load_gim_image(&data).unwrap();
+        assert_eq!(picture.image_data, &indices);
+        assert_eq!(picture.palette_header.map(|header| header.width), Some(16));
+        assert_eq!(picture.palette_data, Some(palette.as_slice()));
+    }
+
+    /// Swaps the chunk headers, image headers and offsets tables of the chunks in `le[start..end]` into `out`.
+    fn swap_chunks(le: &[u8], out: &mut [u8], start: usize, end: usize) {
+        let mut offset = start;
+        while offset < end {
+            let chunk = gim_picture_get_chunk_header(le, offset).unwrap();
+            let mut swapped = chunk;
+            swapped.swap_bytes();
+            out[offset..offset + mem::size_of::<GimChunk>()].copy_from_slice(bytemuck::bytes_of(&swapped));
+            if matches!(chunk.kind(), GimChunkKind::Image | GimChunkKind::Palette) {
+                let header_offset = offset + chunk.data_offs as usize;
+                let header_end = header_offset + mem::size_of::<GimImageHeader>();
+                let header: GimImageHeader = bytemuck::pod_read_unaligned(&le[header_offset..header_end]);
+                let mut swapped = header;
+                swapped.swap_bytes();
+                out[header_offset..header_end].copy_from_slice(bytemuck::bytes_of(&swapped));
+                let offsets_start = header_offset + header.offsets as usize;
+                let offsets_end = offsets_start + header.level_count as usize * header.frame_count as usize * 4;
+                out[offsets_start..offsets_end]
+                    .chunks_exact_mut(4)
+                    .for_each(|entry| entry.reverse());
+            }
+            swap_chunks(le, out, offset + chunk.child_offs as usize, offset + chunk.next_offs as usize);
+            offset += chunk.next_offs as usize;
+        }
+    }
+
+    /// The big endian variant of a little endian GIM file, with the same pixel data.
+    fn to_big_endian(le: &[u8]) -> Vec<u8> {
+        let mut out = le.to_vec();
+        out[..12].chunks_exact_mut(4).for_each(|field| field.reverse());
+        let style = GIM_FORMAT_STYLE_PS3.to_be_bytes();
+        out[8..12].copy_from_slice(&style);
+        swap_chunks(le, &mut out, mem::size_of::<GimHeader>(), le.len());
+        out
+    }
+
+    #[test]
+    fn reads_big_endian_fields() {
+        let indices: Vec<u8> = (0..32 * 8).map(|i| i as u8).collect();
+        let palette: Vec<u8> = (0..256 * 4).map(|i| (i / 4) as u8).collect();
+        let le = write_gim(
+            (GimImageHeader::new(ImageFormat::INDEX8, ImageOrder::PSPImage, 32, 8), &indices),
+            Some((GimImageHeader::new(ImageFormat::RGBA8888, ImageOrder::Normal, 256, 1), &palette)),
+        );
+        let be = to_big_endian(&le);
+        assert!(be.starts_with(b".GIM1.00\0PS3"));
+        assert!(is_big_endian(&be) && is_gim(&be));
+        assert_eq!(file_size(&be).unwrap(), le.len());
+
+        let picture = load_gim_image(&be).unwrap();
+        let header = picture.image_header;
+        assert_eq!(header.image_format(), Some(ImageFormat::INDEX8));
+        assert_eq!(header.image_order(), Some(ImageOrder::PSPImage));
+        assert_eq!((header.width, header.height, header.bpp), (32, 8, 8));
+        assert_eq!(&picture.image_offsets[..], &load_gim_image(&le).unwrap().image_offsets[..]);
+        assert_eq!(picture.image_data, &indices);
+        assert_eq!(picture.palette_header.map(|header| header.width), Some(256));
+        assert_eq!(picture.palette_data, Some(palette.as_slice()));
+    }
+
+    #[test]
+    fn swizzle_round_trips() {
+        for (pitch, height) in [(16, 8), (32, 8), (16, 16), (64, 24), (512, 272)] {
+            let linear: Vec<u8> = (0..pitch * height).map(|i| (i * 7 + i / 251) as u8).collect();
+            let tiled = swizzle(&linear, pitch, height, 16, 8).unwrap();
+            assert_eq!(unswizzle(&tiled, pitch, height, 16, 8).unwrap(), linear, "{} x {}", pitch, height);
+        }
+    }
+
+    #[test]
+    fn swizzle_stores_tiles_one_after_another() {
+        //two tiles side by side, each row of the image is split between them
+        let linear: Vec<u8> = (0..32 * 8).map(|i| i as u8).collect();
+        let tiled = swizzle(&linear, 32, 8, 16, 8).unwrap();
+        assert_eq!(&tiled[..16], &linear[..16]);
+        assert_eq!(&tiled[16..32], &linear[32..48]);
+        assert_eq!(&tiled[128..144], &linear[16..32]);
+    }
+
+    #[test]
+    fn swizzle_rejects_partial_tiles() {
+        let data = vec![0u8; 24 * 12];
+        for (pitch, height) in [(24, 8), (16, 12), (24, 12), (8, 4)] {
+            assert!(swizzle(&data, pitch, height, 16, 8).is_err(), "{} x {}", pitch, height);
+            assert!(unswizzle(&data, pitch, height, 16, 8).is_err(), "{} x {}", pitch, height);
+        }
+    }
+
+    #[test]
+    fn swizzle_rejects_short_data() {
+        assert!(unswizzle(&[0u8; 100], 16, 8, 16, 8).is_err());
+    }
+}
@@ -0,0 +1,2920 @@
+use anyhow::{Context, Result, bail};
+use common::{
+    args::{self, Help},
+    errors::{self, ErrorFormat, ErrorReport},
+    vprintln,
+};
+use lexopt::{Arg, ValueExt};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    io::{Read, Seek, SeekFrom, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+struct Args {
+    filenames: Vec<String>,
+    offset: u64,
+    tx: usize,
+    ty: usize,
+    linear: bool,
+    verbose: bool,
+    quiet: bool,
+    inplace: bool,
+    output_dir: Option<String>,
+    jobs: usize,
+    timings: bool,
+    trim_transparent: bool,
+    dump_chunks: bool,
+    json: bool,
+    report: Option<String>,
+    palette_order: Option<PaletteOrder>,
+    clut_swizzle: bool,
+    palette_channel_order: ChannelOrder,
+    gif: bool,
+    gif_delay: u32,
+    apng: bool,
+    strict: bool,
+    multi: bool,
+    premultiply: bool,
+    colorspace: ColorSpace,
+    bgra: bool,
+    raw: bool,
+    palette_only: bool,
+    split_palette: bool,
+    tile_config: HashMap<gim::ImageFormat, (usize, usize)>,
+    force_format: Option<gim::ImageFormat>,
+    color_key: Option<ColorKey>,
+    transparent_index: Option<usize>,
+    validate: bool,
+    index_depth: Option<u8>,
+    indexed: bool,
+    limit: Option<usize>,
+    palette_export: Option<PaletteExport>,
+    view: bool,
+    follow_symlinks: bool,
+    ext: Option<String>,
+    name: String,
+    contact_sheet: Option<String>,
+    cell_size: usize,
+    downscale: usize,
+    error_format: ErrorFormat,
+    hexdump_on_error: bool,
+}
+
+impl Args {
+    /// Whether the converted images are written out, which `--validate` and `--contact-sheet` turn off.
+    fn writes_output(&self) -> bool {
+        !self.validate && self.contact_sheet.is_none()
+    }
+
+    /// Whether every frame goes into a single animated file, with `--gif` or `--apng`.
+    fn animated(&self) -> bool {
+        self.gif || self.apng
+    }
+
+    /// Whether the output is always RGBA, an animation or a `--raw` dump, which indexed output doesn't apply to.
+    fn rgba_only(&self) -> bool {
+        self.animated() || self.raw
+    }
+}
+
+/// Storage order of the palette entries, used to override what the palette header reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PaletteOrder {
+    Linear,
+    Psp,
+}
+
+/// Which end of a 16 bit palette entry holds the red channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+impl std::str::FromStr for ChannelOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgba" => Ok(ChannelOrder::Rgba),
+            "bgra" => Ok(ChannelOrder::Bgra),
+            _ => Err(format!("invalid channel order '{}', expected 'rgba' or 'bgra'", s)),
+        }
+    }
+}
+
+/// The colour space the output PNGs are marked as being in, which is what viewers use to pick the gamma.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorSpace {
+    /// sRGB, which PSP textures are authored in, written as the `sRGB` chunk with the matching `gAMA`.
+    Srgb,
+    /// Linear light, written as a `gAMA` of 1.0.
+    Linear,
+}
+
+/// The file format of `--palette-export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PaletteExport {
+    /// Adobe Color Table, 256 RGB entries and nothing else.
+    Act,
+    /// GIMP palette, a text file with a line per entry.
+    Gpl,
+}
+
+impl std::str::FromStr for PaletteExport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "act" => Ok(PaletteExport::Act),
+            "gpl" => Ok(PaletteExport::Gpl),
+            _ => Err(format!("invalid palette format '{}', expected 'act' or 'gpl'", s)),
+        }
+    }
+}
+
+/// A palette colour that stands for a transparent pixel, for games that use a colour key instead of alpha.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ColorKey([u8; 3]);
+
+impl std::str::FromStr for ColorKey {
+    type Err = String;
+
+    /// Parses a colour as `RRGGBB` in hex, with an optional leading `#`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let value = (hex.len() == 6 && hex.is_ascii())
+            .then(|| u32::from_str_radix(hex, 16).ok())
+            .flatten()
+            .ok_or_else(|| format!("invalid colour key '{}', expected RRGGBB in hex", s))?;
+        let [_, r, g, b] = value.to_be_bytes();
+        Ok(ColorKey([r, g, b]))
+    }
+}
+
+impl std::str::FromStr for PaletteOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(PaletteOrder::Linear),
+            "psp" => Ok(PaletteOrder::Psp),
+            _ => Err(format!("invalid palette order '{}', expected 'linear' or 'psp'", s)),
+        }
+    }
+}
+
+const HELP: Help = Help {
+    name: "gim2png",
+    version: env!("CARGO_PKG_VERSION"),
+    usage: "[options] <files>...",
+    description: &["Directories are searched recursively for GIM files, found by their header, and mirrored under --output."],
+    options: &[
+        ("-i, --inplace", "output png files in the same directory as the input file"),
+        ("-j, --jobs <n>", "Convert <n> files at once (default 0, the number of CPUs)"),
+        ("-l, --linear", "treat PSP tiled images as linear"),
+        ("-o, --offset <n>", "Skip the first <n> bytes of the input file"),
+        ("-x, --tx <n>", "Tile width (default 0 for auto)"),
+        ("-y, --ty <n>", "Tile height (default 0 for auto)"),
+        ("--apng", "Write an animated PNG with every frame instead of a PNG per frame"),
+        ("--bgra", "Swap the red and blue channels of the output, whatever the source format"),
+        ("--raw", "Write the decoded pixels as <name>.rgba, or <name>.bgra with --bgra, instead of a PNG"),
+        ("--cell-size <n>", "Size in pixels of each thumbnail on the contact sheet (default 128)"),
+        ("--color-key <rrggbb>", "Make the palette entries of this colour transparent"),
+        ("--follow-symlinks", "Descend into symlinked directories when searching directories for GIM files"),
+        (
+            "--contact-sheet <path>",
+            "Write a grid of thumbnails of every input to <path>, and their names to <path>.txt",
+        ),
+        (
+            "--downscale <n>",
+            "Shrink the output by <n>, averaging each <n> x <n> block of pixels, for quick previews",
+        ),
+        ("--ext <ext>", "Only take files with extension <ext> when searching directories, e.g. 'gim'"),
+        ("--dump-chunks", "Print the chunk tree of each file instead of converting it"),
+        ("--force-format <f>", "Decode the image data as format <f> instead of the one in the header"),
+        ("--gif", "Write an animated GIF with every frame instead of a PNG"),
+        ("--gif-delay <ms>", "Delay between GIF or APNG frames in milliseconds (default 100)"),
+        (
+            "--index-depth <4|8>",
+            "Write indexed images as indexed PNGs with 4 or 8 bits per pixel instead of RGBA",
+        ),
+        (
+            "--json",
+            "Print the image and palette headers of each file as a line of JSON instead of converting it",
+        ),
+        (
+            "--indexed",
+            "Write INDEX4 and INDEX8 images as indexed PNGs of the same depth, keeping the palette, see below",
+        ),
+        ("--linear-colorspace", "Mark the output PNGs as linear (gAMA 1.0) instead of sRGB"),
+        ("--name <stem>", "Write the input read from stdin, given as '-', as <stem>.png (default 'stdout')"),
+        ("--multi", "Convert every GIM in files that have several concatenated, as <name>_<n>.png"),
+        (
+            "--palette-channel-order <o>",
+            "Channel order of 16 bit palettes, 'rgba' (red in the low bits, default) or 'bgra'",
+        ),
+        (
+            "--clut-swizzle",
+            "Undo the PSP CLUT order of the palette of tiled INDEX8 images when its header reports PSP order",
+        ),
+        (
+            "--palette-order <o>",
+            "Override the palette header order, 'linear' or 'psp' (32 entry block swizzled)",
+        ),
+        (
+            "--palette-export <f>",
+            "Also write the palette of indexed images as <name>.act or <name>.gpl, <f> is 'act' or 'gpl'",
+        ),
+        (
+            "--palette-only",
+            "Write the palette of indexed images as a swatch, <name>_palette.png, instead of the image",
+        ),
+        (
+            "--split-palette",
+            "Write indexed images as <name>_index.png and <name>_palette.png instead of RGBA, see below",
+        ),
+        ("--srgb", "Mark the output PNGs as sRGB, with sRGB and gAMA chunks (default)"),
+        (
+            "--premultiply",
+            "Premultiply the colour channels by alpha, after any --downscale (lossy, it cannot be undone)",
+        ),
+        ("--report <path>", "Write a JSON report of every input file to <path>"),
+        ("--strict", "Treat warnings about a file as errors and skip it"),
+        (
+            "--tile-config <file>",
+            "Tile sizes per format used instead of the defaults, one '<format> <width> <height>' per line",
+        ),
+        ("--timings", "Print decode and encode times per file, unless --quiet is given, and in total"),
+        ("--transparent-index <n>", "Make palette entry <n> transparent whatever its alpha, usually 0"),
+        (
+            "--trim-transparent",
+            "Crop the output to the bounds of the non transparent pixels, writing the crop box to <name>.trim.json",
+        ),
+        ("--validate", "Decode every file and report OK or FAIL for each, without writing anything"),
+        (
+            "--view",
+            "Write the output of a single file to the temp directory and open it in the default viewer",
+        ),
+        ("--list-formats", "List the image and palette formats that can be converted"),
+    ],
+    notes: &[
+        "With --split-palette the grey level of each pixel of <name>_index.png is its palette index, unswizzled",
+        "and one byte per pixel (0 to 15 for INDEX4), and <name>_palette.png is the palette as a swatch.",
+        "DXT texels are never tiled, only the block order is unswizzled from PSP order unless --linear is set;",
+        "for DXT images -x and -y are in blocks (default 16 bytes of blocks by 8 rows).",
+        "With --indexed the palette goes in the PLTE chunk and its alpha in tRNS; images whose indices don't fit",
+        "the palette, and other formats, are written as RGBA instead.",
+        "A --raw dump is 4 bytes per pixel, row by row with no header, after any trimming and downscaling.",
+        "Images with several mipmap levels are written as <name>_mip<n>.png per level, and several frames as <name>_f<n>.png;",
+        "with both, each frame of each level is <name>_L<level>_F<frame>.png, and --gif or --apng write <name>_mip<n>.",
+    ],
+    short_output: false,
+    threads: true,
+};
+
+fn parse_args<I>(args: I) -> Result<Args, lexopt::Error>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let mut offset = 0;
+    let mut tx = 0;
+    let mut ty = 0;
+    let mut linear = false;
+    let mut inplace = false;
+    let mut jobs = 0;
+    let mut timings = false;
+    let mut trim_transparent = false;
+    let mut dump_chunks = false;
+    let mut json = false;
+    let mut report = None;
+    let mut palette_order = None;
+    let mut clut_swizzle = false;
+    let mut palette_channel_order = ChannelOrder::Rgba;
+    let mut gif = false;
+    let mut gif_delay = 100;
+    let mut apng = false;
+    let mut strict = false;
+    let mut multi = false;
+    let mut premultiply = false;
+    let mut colorspace = ColorSpace::Srgb;
+    let mut bgra = false;
+    let mut raw = false;
+    let mut split_palette = false;
+    let mut palette_only = false;
+    let mut tile_config = HashMap::new();
+    let mut force_format = None;
+    let mut color_key = None;
+    let mut transparent_index = None;
+    let mut validate = false;
+    let mut index_depth = None;
+    let mut indexed = false;
+    let mut palette_export = None;
+    let mut view = false;
+    let mut follow_symlinks = false;
+    let mut ext = None;
+    let mut name = String::from("stdout");
+    let mut contact_sheet = None;
+    let mut cell_size = 128;
+    let mut downscale = 1;
+
+    let common = args::parse(args, &HELP, |arg, parser| {
+        match arg {
+            Arg::Short('x') | Arg::Long("tx") => {
+                tx = parser.value()?.parse()?;
+            }
+            Arg::Short('y') | Arg::Long("ty") => {
+                ty = parser.value()?.parse()?;
+            }
+            Arg::Short('o') | Arg::Long("offset") => {
+                offset = parser.value()?.parse()?;
+            }
+            Arg::Short('l') | Arg::Long("linear") => {
+                linear = true;
+            }
+            Arg::Short('i') | Arg::Long("inplace") => {
+                inplace = true;
+            }
+            Arg::Short('j') | Arg::Long("jobs") => {
+                jobs = parser.value()?.parse()?;
+            }
+            Arg::Long("strict") => {
+                strict = true;
+            }
+            Arg::Long("index-depth") => {
+                index_depth = match parser.value()?.parse()? {
+                    depth @ (4 | 8) => Some(depth),
+                    depth => return Err(format!("invalid index depth {}, expected 4 or 8", depth).into()),
+                };
+            }
+            Arg::Long("indexed") => {
+                indexed = true;
+            }
+            Arg::Long("view") => {
+                view = true;
+            }
+            Arg::Long("follow-symlinks") => {
+                follow_symlinks = true;
+            }
+            Arg::Long("ext") => {
+                ext = Some(parser.value()?.string()?.trim_start_matches('.').to_string());
+            }
+            Arg::Long("name") => {
+                name = parser.value()?.string()?;
+            }
+            Arg::Long("contact-sheet") => {
+                contact_sheet = Some(parser.value()?.string()?);
+            }
+            Arg::Long("cell-size") => {
+                cell_size = parser.value()?.parse()?;
+                if cell_size == 0 {
+                    return Err("--cell-size must be greater than 0".into());
+                }
+            }
+            Arg::Long("downscale") => {
+                downscale = parser.value()?.parse()?;
+                if downscale == 0 {
+                    return Err("--downscale must be greater than 0".into());
+                }
+            }
+            Arg::Long("validate") => {
+                validate = true;
+            }
+            Arg::Long("timings") => {
+                timings = true;
+            }
+            Arg::Long("trim-transparent") => {
+                trim_transparent = true;
+            }
+            Arg::Long("dump-chunks") => {
+                dump_chunks = true;
+            }
+            Arg::Long("json") => {
+                json = true;
+            }
+            Arg::Long("gif") => {
+                gif = true;
+            }
+            Arg::Long("gif-delay") => {
+                gif_delay = parser.value()?.parse()?;
+            }
+            Arg::Long("apng") => {
+                apng = true;
+            }
+            Arg::Long("report") => {
+                report = Some(parser.value()?.string()?);
+            }
+            Arg::Long("multi") => {
+                multi = true;
+            }
+            Arg::Long("tile-config") => {
+                let path = parser.value()?.string()?;
+                tile_config = load_tile_config(&path).map_err(|e| format!("{:#}", e))?;
+            }
+            Arg::Long("color-key") => {
+                color_key = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("transparent-index") => {
+                transparent_index = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("force-format") => {
+                force_format = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("palette-export") => {
+                palette_export = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("palette-only") => {
+                palette_only = true;
+            }
+            Arg::Long("premultiply") => {
+                premultiply = true;
+            }
+            Arg::Long("srgb") => {
+                colorspace = ColorSpace::Srgb;
+            }
+            Arg::Long("linear-colorspace") => {
+                colorspace = ColorSpace::Linear;
+            }
+            Arg::Long("bgra") => {
+                bgra = true;
+            }
+            Arg::Long("raw") => {
+                raw = true;
+            }
+            Arg::Long("split-palette") => {
+                split_palette = true;
+            }
+            Arg::Long("palette-channel-order") => {
+                palette_channel_order = parser.value()?.parse()?;
+            }
+            Arg::Long("clut-swizzle") => {
+                clut_swizzle = true;
+            }
+            Arg::Long("palette-order") => {
+                palette_order = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("list-formats") => {
+                print_formats();
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+        Ok(())
+    })?;
+
+    if common.files.is_empty() {
+        eprintln!("Error: No input file specified.");
+        std::process::exit(1);
+    }
+    if view && common.files.len() > 1 {
+        return Err("--view takes a single input file".into());
+    }
+    if raw && (gif || apng) {
+        return Err("--raw writes every frame on its own, it can't be used with --gif or --apng".into());
+    }
+
+    Ok(Args {
+        filenames: common.files,
+        tx,
+        ty,
+        offset,
+        linear,
+        verbose: common.verbose,
+        quiet: common.quiet,
+        inplace,
+        output_dir: common.output,
+        //0 leaves it to --threads, which is the number of CPUs unless it is given
+        jobs: if jobs > 0 { jobs } else { common.threads },
+        timings,
+        trim_transparent,
+        dump_chunks,
+        json,
+        report,
+        palette_order,
+        clut_swizzle,
+        palette_channel_order,
+        gif,
+        gif_delay,
+        apng,
+        strict,
+        multi,
+        premultiply,
+        colorspace,
+        bgra,
+        raw,
+        palette_only,
+        split_palette,
+        tile_config,
+        force_format,
+        color_key,
+        transparent_index,
+        validate,
+        index_depth,
+        indexed,
+        limit: common.limit,
+        palette_export,
+        view,
+        follow_symlinks,
+        ext,
+        name,
+        contact_sheet,
+        cell_size,
+        downscale,
+        error_format: common.error_format,
+        hexdump_on_error: common.hexdump_on_error,
+    })
+}
+
+/// The input filename that reads the GIM from stdin.
+const STDIN_FILENAME: &str = "-";
+
+/// Image formats that process_image can decode.
+const DECODE_FORMATS: &[gim::ImageFormat] = &[
+    gim::ImageFormat::RGBA8888,
+    gim::ImageFormat::RGBA4444,
+    gim::ImageFormat::INDEX8,
+    gim::ImageFormat::INDEX4,
+    gim::ImageFormat::INDEX16,
+    gim::ImageFormat::DXT1,
+    gim::ImageFormat::DXT3,
+    gim::ImageFormat::DXT5,
+    gim::ImageFormat::DXT1EXT,
+    gim::ImageFormat::DXT3EXT,
+    gim::ImageFormat::DXT5EXT,
+];
+
+/// Palette formats that convert_palette_for_png can convert.
+const PALETTE_FORMATS: &[gim::ImageFormat] = &[
+    gim::ImageFormat::RGBA8888,
+    gim::ImageFormat::RGBA5551,
+    gim::ImageFormat::RGBA5650,
+    gim::ImageFormat::RGBA4444,
+];
+
+fn print_formats() {
+    println!("{:<10} {:<8} Palette", "Format", "Image");
+    for format in gim::ImageFormat::ALL {
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        println!(
+            "{:<10} {:<8} {}",
+            format.to_string(),
+            yes_no(DECODE_FORMATS.contains(&format)),
+            yes_no(PALETTE_FORMATS.contains(&format))
+        );
+    }
+}
+
+/// Runs gim2png with the given command line arguments (not including the program name).
+pub fn run<I>(args: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let args = parse_args(args).context("Failed to parse command line")?;
+    let start = Instant::now();
+    let mut reports = Vec::new();
+    let mut failed = 0;
+    let filenames = expand_directories(&args)?;
+    let filenames = &filenames[..args.limit.unwrap_or(usize::MAX).min(filenames.len())];
+    let mut view_path = None;
+    let mut thumbnails = Vec::new();
+    for ((filename, _), (mut report, result)) in filenames.iter().zip(process_files(filenames, &args)) {
+        if args.view {
+            view_path = report.output.clone();
+        }
+        if let Some(preview) = report.preview.take()
+            && result.is_ok()
+        {
+            thumbnails.push((filename.as_str(), preview));
+        }
+        match &result {
+            Ok(()) if args.validate => println!("OK: {}", filename),
+            Ok(()) => {}
+            Err(e) if args.validate => println!("FAIL: {}: {:#}", filename, e),
+            Err(e) => args.error_format.print(
+                format_args!("Error processing file {}: {:#}", filename, e),
+                ErrorReport {
+                    file: filename,
+                    kind: error_kind(e),
+                    message: &format!("{:#}", e),
+                    offset: error_offset(e).map(|offset| args.offset + offset as u64),
+                },
+            ),
+        }
+        if result.is_err() {
+            failed += 1;
+        }
+        reports.push(report.to_json(filename, &result));
+    }
+    if args.validate {
+        println!("{} of {} files decoded", filenames.len() - failed, filenames.len());
+    }
+    if args.timings {
+        println!("Total time for {} files: {:.2?}", filenames.len(), start.elapsed());
+    }
+    if let Some(report_path) = &args.report {
+        let json = serde_json::to_string_pretty(&reports).context("Failed to serialize report")?;
+        std::fs::write(report_path, json).with_context(|| format!("Failed to write report: {}", report_path))?;
+    }
+    if let Some(sheet_path) = &args.contact_sheet {
+        write_contact_sheet(std::path::Path::new(sheet_path), &thumbnails, &args)?;
+        vprintln!(!args.quiet, "Wrote contact sheet of {} images: {}", thumbnails.len(), sheet_path);
+    }
+    if failed > 0 {
+        bail!("{} of {} files had errors", failed, filenames.len());
+    }
+    if let Some(view_path) = view_path {
+        open_viewer(&view_path)?;
+    }
+    Ok(())
+}
+
+/// Replaces the directories in the input files with the .gim files found in them, recursively and in name order.
+/// Symlinked directories are only descended into with `--follow-symlinks`, and each directory is only visited
+/// once so links back up the tree can't loop.
+fn expand_directories(args: &Args) -> Result<Vec<(String, std::path::PathBuf)>> {
+    let mut filenames = Vec::new();
+    let mut visited = HashSet::new();
+    for filename in &args.filenames {
+        let path = std::path::Path::new(filename);
+        if path.is_dir() {
+            find_gim_files(path, std::path::Path::new(""), args, &mut visited, &mut filenames)?;
+        } else {
+            filenames.push((filename.clone(), std::path::PathBuf::new()));
+        }
+    }
+    Ok(filenames)
+}
+
+/// Adds the GIM files under `dir` to `filenames`, along with the directory each is in relative to the directory
+/// that was searched, `subdir` being that of `dir`.
+fn find_gim_files(
+    dir: &std::path::Path,
+    subdir: &std::path::Path,
+    args: &Args,
+    visited: &mut HashSet<std::path::PathBuf>,
+    filenames: &mut Vec<(String, std::path::PathBuf)>,
+) -> Result<()> {
+    let canonical = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve directory {}", dir.display()))?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    let mut entries = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            if args.follow_symlinks || !path.is_symlink() {
+                let subdir = subdir.join(path.file_name().unwrap_or_default());
+                find_gim_files(&path, &subdir, args, visited, filenames)?;
+            }
+        } else if args
+            .ext
+            .as_ref()
+            .is_some_and(|ext| !path.extension().is_some_and(|e| e.eq_ignore_ascii_case(ext)))
+        {
+            continue;
+        } else if has_gim_header(&path, args.offset) {
+            filenames.push((path.to_string_lossy().into_owned(), subdir.to_path_buf()));
+        } else {
+            vprintln!(args.verbose, "Skipping {}, it is not a GIM file", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Whether the file has a GIM file header at `offset`, without reading the rest of it.
+fn has_gim_header(path: &std::path::Path, offset: u64) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut header)
+        })
+        .is_ok_and(|()| gim::is_gim(&header))
+}
+
+/// Runs [`process_image`] on every file, up to `--jobs` of them at once. The results are in the order of the files
+/// whatever order they finish in, so they are reported the same way every time. Each file writes its own output,
+/// and `println!` keeps the lines the files print whole.
+fn process_files(filenames: &[(String, std::path::PathBuf)], args: &Args) -> Vec<(FileReport, Result<()>)> {
+    let process = |(filename, subdir): &(String, std::path::PathBuf)| {
+        let mut report = FileReport::default();
+        let result = process_image(filename, subdir, args, &mut report);
+        (report, result)
+    };
+    let jobs = args.jobs.min(filenames.len());
+    if jobs <= 1 {
+        return filenames.iter().map(process).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(filenames.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = filenames.get(index) else {
+                        break;
+                    };
+                    let result = process(file);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Opens the file in the system's default viewer for `--view`. The viewer runs on after gim2png exits, so the
+/// file is left in place for it.
+fn open_viewer(path: &std::path::Path) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(path)
+        .spawn()
+        .with_context(|| format!("Failed to start a viewer for {}", path.display()))?;
+    println!("Opened {} in the default viewer", path.display());
+    Ok(())
+}
+
+/// The kind of a conversion failure for `--error-format json`.
+fn error_kind(e: &anyhow::Error) -> &'static str {
+    if e.chain().any(|cause| cause.is::<std::io::Error>()) {
+        "io"
+    } else if e.chain().any(|cause| cause.is::<gim::EmptyImageError>()) {
+        "empty_image"
+    } else {
+        "convert"
+    }
+}
+
+/// The offset in the GIM data that an error is about, for the errors that have one.
+fn error_offset(e: &anyhow::Error) -> Option<usize> {
+    e.chain().find_map(|cause| {
+        cause
+            .downcast_ref::<gim::OffsetError>()
+            .map(|e| e.offset)
+            .or_else(|| cause.downcast_ref::<gim::EmptyImageError>().map(|e| e.header_offset))
+    })
+}
+
+/// Prints the bytes around the offset an error is about for `--hexdump-on-error`. `base` is the offset of
+/// `data` in the input file.
+fn hexdump_error(e: &anyhow::Error, data: &[u8], base: u64, args: &Args) {
+    if let Some(offset) = error_offset(e).filter(|_| args.hexdump_on_error) {
+        eprint!("Bytes around offset 0x{:X}:\n{}", base + offset as u64, errors::hexdump(data, offset, base));
+    }
+}
+
+/// Reports a problem that the conversion can work around, or fails the file with it under `--strict`.
+fn warn(args: &Args, message: String) -> Result<()> {
+    if args.strict {
+        bail!("{} (--strict)", message);
+    }
+    eprintln!("WARNING: {}", message);
+    Ok(())
+}
+
+/// What is known about a single input file once processing has finished (or failed), for `--report`.
+#[derive(Default)]
+struct FileReport {
+    output: Option<std::path::PathBuf>,
+    width: Option<u16>,
+    height: Option<u16>,
+    format: Option<gim::ImageFormat>,
+    /// The first frame of the top level, kept for `--contact-sheet`.
+    preview: Option<Preview>,
+}
+
+/// A decoded image for the contact sheet.
+struct Preview {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+impl FileReport {
+    fn to_json(&self, input: &str, result: &Result<()>) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "input": input,
+            "output": self.output.as_ref().map(|p| p.display().to_string()),
+            "status": if result.is_ok() { "ok" } else { "error" },
+            "width": self.width,
+            "height": self.height,
+            "format": self.format.map(|f| f.to_string()),
+        });
+        if let Err(e) = result {
+            value["error"] = serde_json::Value::String(format!("{:#}", e));
+        }
+        value
+    }
+}
+
+fn process_image(filename: &str, subdir: &std::path::Path, args: &Args, report: &mut FileReport) -> Result<()> {
+    let (input_name, file_data) = if filename == STDIN_FILENAME {
+        (args.name.as_str().into(), read_stdin(args)?)
+    } else {
+        (std::path::Path::new(filename).file_stem().unwrap().to_string_lossy(), read_file(filename, args)?)
+    };
+
+    if args.dump_chunks {
+        return dump_chunks(filename, &file_data);
+    }
+    if args.json {
+        return print_headers_json(filename, &file_data);
+    }
+
+    let output_dir = if args.view {
+        std::env::temp_dir()
+    } else if let Some(output_dir) = &args.output_dir {
+        //files found by searching a directory keep their place in its tree
+        let output_dir = std::path::Path::new(output_dir).join(subdir);
+        if args.writes_output() {
+            std::fs::create_dir_all(&output_dir)
+                .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+        }
+        output_dir
+    } else if args.inplace {
+        std::path::Path::new(filename)
+            .parent()
+            .unwrap_or(std::path::Path::new("."))
+            .to_path_buf()
+    } else {
+        std::path::PathBuf::from(".")
+    };
+    let output_name = if args.offset > 0 {
+        format!("{}_{}", input_name, args.offset)
+    } else {
+        input_name.to_string()
+    };
+
+    if !args.multi {
+        return convert_gim(filename, &file_data, output_dir.join(output_name), args, report)
+            .inspect_err(|e| hexdump_error(e, &file_data, args.offset, args));
+    }
+
+    //concatenated files are split using the size of each one's root chunk, stopping at the first bytes that are not a GIM
+    let mut position = 0;
+    let mut index = 0;
+    while position < file_data.len() {
+        let size = match gim::file_size(&file_data[position..]) {
+            Ok(size) => size,
+            Err(e) if index > 0 => {
+                vprintln!(args.verbose, "Stopped at offset 0x{:X}: {:#}", position, e);
+                break;
+            }
+            Err(e) => {
+                hexdump_error(&e, &file_data, args.offset, args);
+                return Err(e).context("Failed to load image");
+            }
+        };
+        let output_path = output_dir.join(format!("{}_{}", output_name, index));
+        convert_gim(filename, &file_data[position..position + size], output_path, args, report)
+            .inspect_err(|e| hexdump_error(e, &file_data[position..position + size], args.offset + position as u64, args))
+            .with_context(|| format!("Failed to convert GIM {} at offset 0x{:X}", index, position))?;
+        position += size;
+        index += 1;
+    }
+    vprintln!(args.verbose, "Found {} GIM files in {}", index, filename);
+    Ok(())
+}
+
+/// Reads the file from `--offset` to the end.
+fn read_file(filename: &str, args: &Args) -> Result<Vec<u8>> {
+    let mut file = std::fs::File::open(filename).with_context(|| format!("Failed to open file: {}", filename))?;
+    vprintln!(args.verbose, "Opened file: {}", filename);
+
+    //work out file size
+    let file_size = file.metadata()?.len();
+    vprintln!(args.verbose, "File size: {} bytes", file_size);
+
+    if args.offset > 0 {
+        vprintln!(args.verbose, "Seeking to offset: {}", args.offset);
+        Seek::seek(&mut file, SeekFrom::Start(args.offset)).with_context(|| format!("Failed to seek to offset {}", args.offset))?;
+    }
+
+    vprintln!(args.verbose, "Reading file data...");
+    let mut file_data = vec![0u8; (file_size - args.offset) as usize];
+    file.read_exact(&mut file_data).context("Failed to read file data")?;
+    Ok(file_data)
+}
+
+/// Reads all of stdin, which can't be seeked, so the bytes before `--offset` are read and dropped.
+fn read_stdin(args: &Args) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut data)
+        .context("Failed to read stdin")?;
+    vprintln!(args.verbose, "Read {} bytes from stdin", data.len());
+    let offset = usize::try_from(args.offset)
+        .ok()
+        .filter(|&offset| offset <= data.len())
+        .with_context(|| format!("Offset {} is past the end of the {} bytes read from stdin", args.offset, data.len()))?;
+    data.drain(..offset);
+    Ok(data)
+}
+
+/// Converts the GIM file in `file_data`, writing it to `output_path` with the extension of the output format.
+fn convert_gim(
+    filename: &str,
+    file_data: &[u8],
+    mut output_path: std::path::PathBuf,
+    args: &Args,
+    report: &mut FileReport,
+) -> Result<()> {
+    let picture = gim::load_gim_image(file_data).context("Failed to load image")?;
+    let mut format: gim::ImageFormat = picture.image_header.image_format().context("Failed to get image format")?;
+    let order: gim::ImageOrder = picture.image_header.image_order().context("Failed to get image order")?;
+    //a single plane image has 8 bits per pixel whatever format the header has, unless the format is forced
+    let plane = picture
+        .image_header
+        .single_plane()
+        .filter(|_| picture.image_header.bpp == 8 && args.force_format.is_none());
+
+    let (width, height) = picture.dimensions();
+    report.width = Some(width);
+    report.height = Some(height);
+    report.format = Some(format);
+
+    vprintln!(args.verbose, "GIM Image Format: {:?}", format);
+    vprintln!(args.verbose, "GIM Image Order: {:?}", order);
+
+    //guard against corrupt headers before any of the size maths below
+    if width == 0 || height == 0 {
+        bail!("Error: GIM Image has invalid dimensions {} x {}.", width, height);
+    }
+    if picture.image_header.bpp == 0 {
+        bail!("Error: GIM Image has invalid bits per pixel: 0.");
+    }
+    if args.force_format.is_none() && plane.is_none() && picture.image_header.bpp != format.bits_per_pixel() {
+        //an indexed image whose bpp fits a different index size most likely has the wrong format in its header
+        let bpp_format = gim::ImageFormat::indexed_with_bpp(picture.image_header.bpp).filter(|_| format.is_indexed());
+        match bpp_format {
+            Some(bpp_format) => {
+                warn(
+                    args,
+                    format!("{} image has {} bits per pixel, decoding it as {}", format, picture.image_header.bpp, bpp_format),
+                )?;
+                format = bpp_format;
+            }
+            None => warn(
+                args,
+                format!(
+                    "{} image has {} bits per pixel but the format has {}",
+                    format,
+                    picture.image_header.bpp,
+                    format.bits_per_pixel()
+                ),
+            )?,
+        }
+    }
+    if picture.image_header.pitch_align == 0 || picture.image_header.height_align == 0 {
+        bail!(
+            "Error: GIM Image has invalid alignment, pitch align: {}, height align: {}.",
+            picture.image_header.pitch_align,
+            picture.image_header.height_align
+        );
+    }
+
+    if let Some(force_format) = args.force_format {
+        //the header may be wrong about the format but the data must still be big enough for the new one
+        let (iw, ih) = picture.aligned_dimensions();
+        let needed = iw * ih * force_format.bits_per_pixel() as usize / 8;
+        if picture.image_data.len() < needed {
+            bail!(
+                "Error: {} x {} at {} bpp needs {} bytes but the image data is only {} bytes, cannot force format {}.",
+                iw,
+                ih,
+                force_format.bits_per_pixel(),
+                needed,
+                picture.image_data.len(),
+                force_format
+            );
+        }
+        vprintln!(args.verbose, "Forcing format {} instead of {}", force_format, format);
+        format = force_format;
+    }
+
+    if args.palette_only && args.writes_output() {
+        let Some(pal_data) = load_palette(&picture, format, order, args)? else {
+            bail!("Error: GIM Image Format '{}' has no palette.", format);
+        };
+        let pal_data = declared_palette(&picture, &pal_data)?;
+        let entries = pal_data.len() / 4;
+        if let Some(palette_export) = args.palette_export {
+            export_palette(&output_path, pal_data, palette_export, args)?;
+        }
+        output_path.as_mut_os_string().push("_palette.png");
+        write_palette_swatch(&output_path, pal_data, args.colorspace)?;
+        vprintln!(!args.quiet, "Extracted palette file: {} ({} entries)", output_path.display(), entries);
+        report.output = Some(output_path);
+        return Ok(());
+    }
+
+    //the samples of a single plane are stored like INDEX8 data, they are decoded through a ramp palette
+    if let Some(plane) = plane {
+        vprintln!(args.verbose, "Decoding the {:?} plane only", plane);
+        format = gim::ImageFormat::INDEX8;
+    }
+
+    let frame_count = picture.image_header.frame_count.max(1) as usize;
+    let level_count = picture.image_header.level_count.max(1) as usize;
+    let separate_frames = frame_count > 1 && !args.animated();
+    if level_count > 1 && separate_frames {
+        vprintln!(
+            args.verbose,
+            "Image has {} mipmap levels of {} frames, writing each as <name>_L<level>_F<frame>",
+            level_count,
+            frame_count
+        );
+    } else if level_count > 1 {
+        vprintln!(args.verbose, "Image has {} mipmap levels, writing each as <name>_mip<n>", level_count);
+    } else if separate_frames {
+        vprintln!(args.verbose, "Image has {} frames, writing each as <name>_f<n>", frame_count);
+    }
+
+    vprintln!(args.verbose, "Image width: {}, height: {}", width, height);
+    vprintln!(
+        args.verbose,
+        "Image pitch align: {}, height align: {}",
+        picture.image_header.pitch_align,
+        picture.image_header.height_align
+    );
+
+    //the data is aligned by these parameters from the header
+    let (iw, ih) = picture.aligned_dimensions();
+
+    if (width as usize) < iw {
+        vprintln!(args.verbose, "NOTE: width {} aligned to {}", width, iw);
+    }
+    if (height as usize) < ih {
+        vprintln!(args.verbose, "NOTE: height {} aligned to {}", height, ih);
+    }
+    vprintln!(args.verbose, "Image data dimensions: {} x {}", iw, ih);
+
+    if !DECODE_FORMATS.contains(&format) {
+        bail!("Error: GIM Image Format '{}' not supported for conversion.", format);
+    }
+
+    if picture.image_header.plane_mask != 0 && plane.is_none() {
+        warn(args, format!("unknown plane mask 0x{:X}, decoding all planes", picture.image_header.plane_mask))?;
+    }
+    //more data than the aligned dimensions need suggests the width or height in the header is wrong, except
+    //for 1D textures that can still have the height padding in the file
+    let expected_size = iw * ih * picture.image_header.bpp as usize / 8;
+    if frame_count == 1
+        && level_count == 1
+        && !picture.is_one_dimensional()
+        && picture.image_data.len() > expected_size.next_multiple_of(16)
+    {
+        warn(
+            args,
+            format!(
+                "image data is {} bytes but {} x {} at {} bpp only needs {}",
+                picture.image_data.len(),
+                iw,
+                ih,
+                picture.image_header.bpp,
+                expected_size
+            ),
+        )?;
+    }
+
+    let mut pal_data = match plane {
+        Some(plane) => Some(Cow::Owned(plane_palette(plane))),
+        None => load_palette(&picture, format, order, args)?,
+    };
+
+    if let Some(palette_export) = args.palette_export
+        && args.writes_output()
+    {
+        match (&pal_data, plane) {
+            (Some(pal_data), None) => export_palette(&output_path, declared_palette(&picture, pal_data)?, palette_export, args)?,
+            _ => warn(args, format!("--palette-export only applies to indexed images, {} has no palette", format))?,
+        }
+    }
+
+    //the palette is written as it is declared, before anything below changes its colours
+    let split_palette = args.split_palette && !args.rgba_only() && plane.is_none();
+    match &pal_data {
+        Some(pal_data) if split_palette && args.writes_output() => {
+            let swatch_path = suffixed_path(&output_path, "_palette.png");
+            write_palette_swatch(&swatch_path, declared_palette(&picture, pal_data)?, args.colorspace)?;
+            vprintln!(!args.quiet, "Extracted palette file: {}", swatch_path.display());
+        }
+        None if split_palette => warn(args, format!("--split-palette only applies to indexed images, writing {} as RGBA", format))?,
+        _ => {}
+    }
+
+    if args.bgra
+        && let Some(pal_data) = &mut pal_data
+    {
+        swap_red_blue(pal_data.to_mut());
+    }
+    //the decoded pixels are premultiplied after any downscaling, so they are only weighted by alpha once, but the
+    //palette written with indexed and GIF output has to be premultiplied to match them
+    let mut output_palette = pal_data.clone();
+    if args.premultiply
+        && let Some(output_palette) = &mut output_palette
+    {
+        premultiply_alpha(output_palette.to_mut());
+    }
+
+    //indexed output only makes sense for PNGs of indexed images, a GIF is always indexed
+    let native_depth = match format {
+        gim::ImageFormat::INDEX4 => Some(4),
+        gim::ImageFormat::INDEX8 => Some(8),
+        _ => None,
+    };
+    let index_depth = args
+        .index_depth
+        .or(native_depth.filter(|_| args.indexed))
+        .filter(|_| !args.rgba_only() && plane.is_none());
+    if index_depth.is_some() && pal_data.is_none() {
+        warn(args, format!("--index-depth only applies to indexed images, writing {} as RGBA", format))?;
+    } else if args.indexed && args.index_depth.is_none() && native_depth.is_none() && pal_data.is_some() {
+        vprintln!(args.verbose, "--indexed only applies to INDEX4 and INDEX8 images, writing {} as RGBA", format);
+    }
+
+    let mut decode_time = std::time::Duration::ZERO;
+    let mut encode_time = std::time::Duration::ZERO;
+    for level in 0..level_count {
+        //DXT blocks cover the real size of the level, the padding to 4 pixels is cropped off when decoding
+        let (iw, ih) = match format.dxt_block_size() {
+            Some(_) => {
+                let (width, height) = picture.level_dimensions(level);
+                (width as usize, height as usize)
+            }
+            None => picture.aligned_level_dimensions(level),
+        };
+        let decode_start = Instant::now();
+        let mut progress = |done: usize, total: usize| {
+            if total > 1 {
+                vprintln!(args.verbose, "Decoded frame {} of {} of level {}", done, total, level);
+            }
+        };
+        let (mut frames, mut index_frames, iw, ih) = match (index_depth.is_some() || split_palette, pal_data.as_deref()) {
+            //the indices are decoded through an identity palette, so they come out in the red channel, and the
+            //colours are looked up in the real palette afterwards
+            (true, Some(pal_data)) => {
+                let identity: Vec<u8> = (0..=255).flat_map(|i| [i, 0, 0, 255]).collect();
+                let (index_frames, iw, ih) = decode_frames(&picture, format, Some(&identity), level, iw, ih, args, &mut progress)
+                    .with_context(|| format!("Failed to decode level {}", level))?;
+                let frames = index_frames
+                    .iter()
+                    .map(|indices| apply_palette(pal_data, indices))
+                    .collect::<Result<_>>()?;
+                (frames, Some(index_frames), iw, ih)
+            }
+            _ => {
+                let (frames, iw, ih) = decode_frames(&picture, format, pal_data.as_deref(), level, iw, ih, args, &mut progress)
+                    .with_context(|| format!("Failed to decode level {}", level))?;
+                (frames, None, iw, ih)
+            }
+        };
+        decode_time += decode_start.elapsed();
+
+        let (mut iw, mut ih) = (iw, ih);
+        let trim = trim_bounds(&frames, iw, ih, filename, args)?;
+        let untrimmed_size = (iw, ih);
+        if let Some((x, y, w, h)) = trim {
+            frames = frames.iter().map(|out| crop_rgba(out, iw, x, y, w, h)).collect();
+            index_frames = index_frames.map(|index_frames| index_frames.iter().map(|out| crop_rgba(out, iw, x, y, w, h)).collect());
+            (iw, ih) = (w, h);
+        }
+        if args.downscale > 1 {
+            let n = args.downscale;
+            frames = frames.iter().map(|out| downscale_rgba(out, iw, ih, n)).collect();
+            //indices can't be averaged, so each block keeps the index of its top left pixel
+            index_frames =
+                index_frames.map(|index_frames| index_frames.iter().map(|out| downscale_nearest(out, iw, ih, n)).collect());
+            (iw, ih) = (iw.div_ceil(n), ih.div_ceil(n));
+            vprintln!(args.verbose, "Downscaled to {} x {}", iw, ih);
+        }
+        if args.premultiply {
+            frames.iter_mut().for_each(|out| premultiply_alpha(out));
+        }
+        if args.contact_sheet.is_some() && level == 0 {
+            report.preview = Some(Preview {
+                width: iw,
+                height: ih,
+                rgba: frames[0].clone(),
+            });
+        }
+        if !args.writes_output() {
+            continue;
+        }
+
+        let encode_start = Instant::now();
+        //animations and the trim box cover every frame of the level
+        let level_path = suffixed_path(&output_path, &image_suffix(level, level_count, 0, 1));
+        if let Some(bounds) = trim {
+            write_trim_bounds(&suffixed_path(&level_path, ".trim.json"), bounds, untrimmed_size)?;
+        }
+        if args.gif {
+            let gif_path = suffixed_path(&level_path, ".gif");
+            vprintln!(args.verbose, "Writing output file: {}", gif_path.display());
+            //the ramp palette of a single plane has no colours worth keeping
+            let palette = output_palette.as_deref().filter(|_| plane.is_none());
+            write_gif(&gif_path, iw, ih, &frames, palette, args.gif_delay)?;
+            vprintln!(!args.quiet, "Extracted texture file: {}", gif_path.display());
+            report.output = Some(gif_path);
+        } else if args.apng {
+            let png_path = suffixed_path(&level_path, ".png");
+            vprintln!(args.verbose, "Writing output file: {}", png_path.display());
+            write_apng(&png_path, iw, ih, &frames, args.gif_delay, args.colorspace)?;
+            vprintln!(!args.quiet, "Extracted texture file: {}", png_path.display());
+            report.output = Some(png_path);
+        } else if args.raw {
+            for (frame, rgba) in frames.iter().enumerate() {
+                let frame_path = suffixed_path(&output_path, &image_suffix(level, level_count, frame, frame_count));
+                let raw_path = suffixed_path(&frame_path, if args.bgra { ".bgra" } else { ".rgba" });
+                vprintln!(args.verbose, "Writing output file: {}", raw_path.display());
+                std::fs::write(&raw_path, rgba).with_context(|| format!("Failed to write {}", raw_path.display()))?;
+                vprintln!(!args.quiet, "Extracted texture file: {} ({} x {})", raw_path.display(), iw, ih);
+                report.output = Some(raw_path);
+            }
+        } else {
+            for (frame, rgba) in frames.iter().enumerate() {
+                let frame_path = suffixed_path(&output_path, &image_suffix(level, level_count, frame, frame_count));
+                let png_path = suffixed_path(
+                    &frame_path,
+                    if split_palette && index_frames.is_some() {
+                        "_index.png"
+                    } else {
+                        ".png"
+                    },
+                );
+                vprintln!(args.verbose, "Writing output file: {}", png_path.display());
+                match (&index_frames, index_depth, output_palette.as_deref()) {
+                    (Some(index_frames), _, _) if split_palette => {
+                        write_plane_png(&png_path, iw, ih, &index_frames[frame], false, args.colorspace)?
+                    }
+                    (Some(index_frames), Some(depth), Some(pal_data)) => {
+                        let indices: Vec<u8> = index_frames[frame].chunks_exact(4).map(|p| p[0]).collect();
+                        //--indexed picks the depth itself, so an image it can't represent is expanded rather than failed
+                        let entries = (pal_data.len() / 4).min(1 << depth);
+                        if args.index_depth.is_none() && indices.iter().any(|&index| index as usize >= entries) {
+                            warn(
+                                args,
+                                format!("{} has indices past its {} palette entries, writing it as RGBA", filename, entries),
+                            )?;
+                            write_png(&png_path, iw, ih, rgba, args.colorspace)?;
+                        } else {
+                            write_indexed_png(&png_path, iw, ih, &indices, pal_data, depth, args.colorspace)?;
+                        }
+                    }
+                    _ if plane.is_some() => {
+                        write_plane_png(&png_path, iw, ih, rgba, plane == Some(gim::Plane::Alpha), args.colorspace)?
+                    }
+                    _ => write_png(&png_path, iw, ih, rgba, args.colorspace)?,
+                }
+                vprintln!(!args.quiet, "Extracted texture file: {}", png_path.display());
+                report.output = Some(png_path);
+            }
+        }
+        encode_time += encode_start.elapsed();
+    }
+
+    if args.timings && !args.quiet {
+        println!("Timings for {}: decode {:.2?}, encode {:.2?}", filename, decode_time, encode_time);
+    }
+    Ok(())
+}
+
+/// Returns the path with `suffix` added to the end of its file name.
+fn suffixed_path(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// The suffix of the output of a level and frame of an image, `_mip<level>` when it has several mipmap levels,
+/// `_f<frame>` when it has several frames and `_L<level>_F<frame>` when it has both.
+fn image_suffix(level: usize, level_count: usize, frame: usize, frame_count: usize) -> String {
+    match (level_count > 1, frame_count > 1) {
+        (true, true) => format!("_L{}_F{}", level, frame),
+        (true, false) => format!("_mip{}", level),
+        (false, true) => format!("_f{}", frame),
+        (false, false) => String::new(),
+    }
+}
+
+/// Decodes every frame of a mipmap level, `iw` x `ih` being the aligned size of the level. Returns the frames
+/// and the width and height they were decoded with, see [`decode_image`]. `progress` is called with the number
+/// of frames decoded so far and the frame count after each frame, for feedback on long decodes.
+#[allow(clippy::too_many_arguments)]
+fn decode_frames(
+    picture: &gim::GimPicture,
+    format: gim::ImageFormat,
+    pal_data: Option<&[u8]>,
+    level: usize,
+    mut iw: usize,
+    mut ih: usize,
+    args: &Args,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Vec<Vec<u8>>, usize, usize)> {
+    let frame_count = picture.image_header.frame_count.max(1) as usize;
+    let single_image = frame_count == 1 && picture.image_header.level_count <= 1;
+    if !single_image {
+        picture.check_layout()?;
+    }
+    let mut frames = Vec::with_capacity(frame_count);
+    for frame in 0..frame_count {
+        let image_data = if single_image {
+            picture.image_data
+        } else {
+            let index = picture.image_index(level, frame);
+            picture
+                .image_data_at(index)
+                .with_context(|| format!("Failed to locate frame {}", frame))?
+        };
+        let (mut out, frame_iw, frame_ih) = decode_image(picture, format, image_data, pal_data, iw, ih, args)
+            .with_context(|| format!("Failed to decode frame {}", frame))?;
+        if frame > 0 && (frame_iw, frame_ih) != (iw, ih) {
+            bail!(
+                "Error: frame {} decoded as {} x {} but the previous frames are {} x {}",
+                frame,
+                frame_iw,
+                frame_ih,
+                iw,
+                ih
+            );
+        }
+        (iw, ih) = (frame_iw, frame_ih);
+        if args.bgra && pal_data.is_none() {
+            swap_red_blue(&mut out);
+        }
+        frames.push(out);
+        progress(frames.len(), frame_count);
+    }
+    Ok((frames, iw, ih))
+}
+
+/// Puts the blocks of DXT data in linear order, a row of blocks at a time. PSP order swizzles the blocks rather than
+/// the texels, in tiles of 16 bytes by 8 rows of blocks by default, which is undone unless `--linear` is set. When
+/// the blocks don't make whole tiles they are left as they are, with a warning.
+fn dxt_linear_blocks<'a>(
+    data: &'a [u8],
+    format: gim::ImageFormat,
+    block_size: usize,
+    blocks_x: usize,
+    blocks_y: usize,
+    order: gim::ImageOrder,
+    args: &Args,
+) -> Result<Cow<'a, [u8]>> {
+    if order != gim::ImageOrder::PSPImage || args.linear {
+        return Ok(Cow::Borrowed(data));
+    }
+    let blocks = blocks_x * blocks_y;
+    let (tw, th) = tile_size(args, format, (16 / block_size, 8), blocks_x, blocks_y)?;
+    vprintln!(args.verbose, "Block tile dimensions: {} x {} blocks", tw, th);
+    let Some(blocks_data) = data.get(..blocks * block_size) else {
+        bail!("Error: not enough image data for {} blocks (data length {})", blocks, data.len());
+    };
+    match gim::unswizzle(blocks_data, blocks_x * block_size, blocks_y, tw * block_size, th) {
+        Ok(unswizzled) => {
+            vprintln!(args.verbose, "Unswizzled block order from PSP order");
+            Ok(Cow::Owned(unswizzled))
+        }
+        Err(e) => {
+            warn(args, format!("{:#}, the blocks were left in PSP order", e))?;
+            Ok(Cow::Borrowed(data))
+        }
+    }
+}
+
+/// Decodes DXT data to RGBA, `iw` x `ih` being the real size of the image. Whole 4 x 4 blocks are decoded and
+/// the image cropped to its size afterwards. Only the order of the blocks depends on the image order, the texels
+/// of a block are never tiled, so for them `--linear` is implied.
+///
+/// The PSP stores the blocks differently from DDS files: a DXT1 block is the 32 bits of 2 bit indices first,
+/// then the two RGB565 colours, with red in the low bits like every other PSP 16 bit format. DXT3 and DXT5
+/// blocks have that colour block first and the 8 bytes of alpha after it. The EXT formats have the same blocks.
+fn decode_dxt(
+    image_data: &[u8],
+    format: gim::ImageFormat,
+    order: gim::ImageOrder,
+    iw: usize,
+    ih: usize,
+    args: &Args,
+) -> Result<Vec<u8>> {
+    let block_size = format.dxt_block_size().context("Not a DXT format")?;
+    let (blocks_x, blocks_y) = (iw.div_ceil(4), ih.div_ceil(4));
+    let data = dxt_linear_blocks(image_data, format, block_size, blocks_x, blocks_y, order, args)?;
+    if data.len() < blocks_x * blocks_y * block_size {
+        bail!("Error: not enough image data for {} x {} blocks (data length {})", blocks_x, blocks_y, data.len());
+    }
+
+    let stride = blocks_x * 4;
+    let mut out = vec![0u8; stride * blocks_y * 4 * 4];
+    for (index, block) in data.chunks_exact(block_size).take(blocks_x * blocks_y).enumerate() {
+        let texels = match format {
+            gim::ImageFormat::DXT1 | gim::ImageFormat::DXT1EXT => decode_dxt_colour_block(block, false),
+            gim::ImageFormat::DXT3 | gim::ImageFormat::DXT3EXT => {
+                let mut texels = decode_dxt_colour_block(block, true);
+                //explicit alpha, 4 bits per texel with the first texel in the low bits
+                let alpha = u64::from_le_bytes(block[8..16].try_into()?);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+                }
+                texels
+            }
+            _ => {
+                let mut texels = decode_dxt_colour_block(block, true);
+                let alpha = decode_dxt5_alpha(&block[8..16]);
+                for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                    texel[3] = alpha;
+                }
+                texels
+            }
+        };
+        let (bx, by) = (index % blocks_x * 4, index / blocks_x * 4);
+        for (i, texel) in texels.iter().enumerate() {
+            let dst = ((by + i / 4) * stride + bx + i % 4) * 4;
+            out[dst..dst + 4].copy_from_slice(texel);
+        }
+    }
+    Ok(crop_rgba(&out, stride, 0, 0, iw, ih))
+}
+
+/// Decodes the interpolated alpha of a DXT5 block to the alpha of its 16 texels. The PSP puts the 48 bits of
+/// 3 bit indices first, as a u32 of the low bits and a u16 of the high bits, then the two alpha endpoints. When
+/// the first endpoint is greater there are six steps between them, otherwise four plus 0 and 255.
+fn decode_dxt5_alpha(block: &[u8]) -> [u8; 16] {
+    let indices = u32::from_le_bytes([block[0], block[1], block[2], block[3]]) as u64
+        | (u16::from_le_bytes([block[4], block[5]]) as u64) << 32;
+    let (a0, a1) = (block[6] as u32, block[7] as u32);
+    let alphas: [u8; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            _ => ((a0 * (8 - i as u32) + a1 * (i as u32 - 1)) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            6 => 0,
+            7 => 255,
+            _ => ((a0 * (6 - i as u32) + a1 * (i as u32 - 1)) / 5) as u8,
+        })
+    };
+    std::array::from_fn(|i| alphas[(indices >> (i * 3)) as usize & 7])
+}
+
+/// Decodes the colour half of a DXT block to its 16 texels, row by row. With `opaque` unset, a block whose first
+/// colour is not greater than the second has three colours and transparent black, as DXT1 allows.
+fn decode_dxt_colour_block(block: &[u8], opaque: bool) -> [[u8; 4]; 16] {
+    let indices = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+    let c0 = u16::from_le_bytes([block[4], block[5]]);
+    let c1 = u16::from_le_bytes([block[6], block[7]]);
+    let expand = |c: u16| {
+        let (r, g, b) = ((c & 0x1F) as u32, ((c >> 5) & 0x3F) as u32, ((c >> 11) & 0x1F) as u32);
+        [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+    };
+    let (e0, e1) = (expand(c0), expand(c1));
+    let mix = |a: u32, b: u32, wa: u32, wb: u32, div: u32| ((a * wa + b * wb) / div) as u8;
+    let colours: [[u8; 4]; 4] = if c0 > c1 || opaque {
+        [
+            [e0[0] as u8, e0[1] as u8, e0[2] as u8, 255],
+            [e1[0] as u8, e1[1] as u8, e1[2] as u8, 255],
+            [
+                mix(e0[0], e1[0], 2, 1, 3),
+                mix(e0[1], e1[1], 2, 1, 3),
+                mix(e0[2], e1[2], 2, 1, 3),
+                255,
+            ],
+            [
+                mix(e0[0], e1[0], 1, 2, 3),
+                mix(e0[1], e1[1], 1, 2, 3),
+                mix(e0[2], e1[2], 1, 2, 3),
+                255,
+            ],
+        ]
+    } else {
+        [
+            [e0[0] as u8, e0[1] as u8, e0[2] as u8, 255],
+            [e1[0] as u8, e1[1] as u8, e1[2] as u8, 255],
+            [
+                mix(e0[0], e1[0], 1, 1, 2),
+                mix(e0[1], e1[1], 1, 1, 2),
+                mix(e0[2], e1[2], 1, 1, 2),
+                255,
+            ],
+            [0, 0, 0, 0],
+        ]
+    };
+    std::array::from_fn(|i| colours[(indices >> (i * 2)) as usize & 3])
+}
+
+/// Finds the box `(x, y, width, height)` to crop the frames to when `--trim-transparent` is set, the union of the
+/// bounds of their non transparent pixels. Every frame is cropped to the same box so they stay aligned, fully
+/// transparent frames are left out of it. Returns `None` when there is nothing to trim, with a warning when the
+/// whole image is fully opaque or fully transparent.
+fn trim_bounds(
+    frames: &[Vec<u8>],
+    iw: usize,
+    ih: usize,
+    filename: &str,
+    args: &Args,
+) -> Result<Option<(usize, usize, usize, usize)>> {
+    if !args.trim_transparent {
+        return Ok(None);
+    }
+    let frame_bounds: Vec<_> = frames.iter().map(|out| transparent_bounds(out, iw, ih)).collect();
+    let transparent_frames = frame_bounds.iter().filter(|bounds| bounds.is_none()).count();
+    let bounds = frame_bounds
+        .into_iter()
+        .flatten()
+        .reduce(|(ax, ay, aw, ah), (bx, by, bw, bh)| {
+            let (x, y) = (ax.min(bx), ay.min(by));
+            (x, y, (ax + aw).max(bx + bw) - x, (ay + ah).max(by + bh) - y)
+        });
+    match bounds {
+        Some((x, y, w, h)) if (w, h) != (iw, ih) => {
+            if transparent_frames > 0 {
+                vprintln!(args.verbose, "NOTE: {} fully transparent frames left out of the trim bounds", transparent_frames);
+            }
+            vprintln!(!args.quiet, "Trimmed {} to {} x {} at offset ({}, {})", filename, w, h, x, y);
+            Ok(Some((x, y, w, h)))
+        }
+        Some(_) if frames.iter().all(|out| out.chunks_exact(4).all(|p| p[3] == 255)) => {
+            warn(args, "image is fully opaque, not trimming".to_string())?;
+            Ok(None)
+        }
+        Some(_) => {
+            vprintln!(args.verbose, "NOTE: nothing to trim, the image has no transparent border");
+            Ok(None)
+        }
+        None => {
+            warn(args, "image is fully transparent, not trimming".to_string())?;
+            Ok(None)
+        }
+    }
+}
+
+/// Writes where `--trim-transparent` cropped an image from, so it can be put back in place: the box within the
+/// decoded image, before any `--downscale`, and the size of the image it was cropped from.
+fn write_trim_bounds(
+    path: &std::path::Path,
+    (x, y, width, height): (usize, usize, usize, usize),
+    untrimmed: (usize, usize),
+) -> Result<()> {
+    let value = serde_json::json!({
+        "x": x,
+        "y": y,
+        "width": width,
+        "height": height,
+        "original_width": untrimmed.0,
+        "original_height": untrimmed.1,
+    });
+    let json = serde_json::to_string_pretty(&value).context("Failed to serialize trim bounds")?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write trim bounds: {}", path.display()))
+}
+
+/// Decodes one image of the picture (a single frame or level) from `format` to RGBA. `image_data` is the data
+/// of that image and `pal_data` the already prepared RGBA palette for indexed formats. Returns the decoded pixels
+/// and the width and height used. When there is not enough data the width can be narrower than `iw` for RGBA8888
+/// images, and the height shorter than `ih` for indexed ones.
+fn decode_image(
+    picture: &gim::GimPicture,
+    format: gim::ImageFormat,
+    image_data: &[u8],
+    pal_data: Option<&[u8]>,
+    mut iw: usize,
+    mut ih: usize,
+    args: &Args,
+) -> Result<(Vec<u8>, usize, usize)> {
+    let order = match picture.image_header.image_order().context("Failed to get image order")? {
+        //there is nothing to tile in a single row
+        _ if picture.is_one_dimensional() => gim::ImageOrder::Normal,
+        order => order,
+    };
+    if format.dxt_block_size().is_some() {
+        return Ok((decode_dxt(image_data, format, order, iw, ih, args)?, iw, ih));
+    }
+
+    //indexed data that runs out before the declared height most likely has the height padded in the header,
+    //so decode the rows that are there, in whole tile rows for tiled data
+    let row_bytes = iw * format.bits_per_pixel() as usize / 8;
+    if format.is_indexed() && row_bytes * ih > image_data.len() {
+        let mut new_ih = image_data.len() / row_bytes.max(1);
+        if order == gim::ImageOrder::PSPImage && !args.linear {
+            let (_, th) = tile_size(args, format, (1, 8), iw, ih)?;
+            new_ih -= new_ih % th;
+        }
+        warn(
+            args,
+            format!(
+                "not enough data for the height, using the data length to calc height. Aligned height was: {} now: {}",
+                ih, new_ih
+            ),
+        )?;
+        if new_ih == 0 {
+            bail!("Error: not enough image data for a single row (data length {})", image_data.len());
+        }
+        ih = new_ih;
+    }
+
+    let out = if format == gim::ImageFormat::RGBA8888 {
+        if (ih * iw * 4) > image_data.len() {
+            //calculated image data is not right, lets stick with the height and div by that to get width
+            let new_iw = image_data.len() / 4 / ih;
+            warn(
+                args,
+                format!(
+                    "not enough data for pitch, using aligned height to calc width. Aligned width was: {} now: {}",
+                    iw, new_iw
+                ),
+            )?;
+            if new_iw == 0 {
+                bail!("Error: not enough image data for a single row (data length {})", image_data.len());
+            }
+            iw = new_iw;
+        }
+
+        let mut out = vec![0u8; iw * ih * 4];
+        if order == gim::ImageOrder::PSPImage && !args.linear {
+            // read as 4 x 8 tiles and convert to linear output
+            let (tw, th) = tile_size(args, format, (4, 8), iw, ih)?;
+            let tiles_x = iw / tw;
+            let tiles_y = ih / th;
+
+            vprintln!(args.verbose, "Tile dimensions: {} x {}", tw, th);
+            vprintln!(args.verbose, "Number of tiles: {} x {}", tiles_x, tiles_y);
+
+            for ty in 0..tiles_y {
+                //vprintln!(args.verbose, "Processing tile row {}/{}", ty + 1, tiles_y);
+                for tx in 0..tiles_x {
+                    let tile_index = ty * tiles_x + tx;
+                    let tile_offset = tile_index * tw * th;
+
+                    for y in 0..th {
+                        for x in 0..tw {
+                            let src = (tile_offset + y * tw + x) * 4;
+
+                            // Convert tile coords -> image coords
+                            let px = tx * tw + x;
+                            let py = ty * th + y;
+                            let dst = (py * iw + px) * 4;
+
+                            //vprintln!(args.verbose, "Tile ({}, {}) Pixel ({}, {}) -> Image Pixel ({}, {})", tx, ty, x, y, px, py);
+
+                            if src + 3 >= image_data.len() {
+                                bail!("Error: source index {} out of bounds (data length {})", src + 3, image_data.len());
+                            }
+
+                            out[dst] = image_data[src];
+                            out[dst + 1] = image_data[src + 1];
+                            out[dst + 2] = image_data[src + 2];
+                            out[dst + 3] = image_data[src + 3];
+                        }
+                    }
+                }
+            }
+        } else {
+            //linear image data
+            for y in 0..ih {
+                //vprintln!(args.verbose, "Processing image row {}/{}", y + 1, ih);
+                for x in 0..iw {
+                    let src = (y * iw + x) * 4;
+                    let dst = (y * iw + x) * 4;
+
+                    if src + 3 >= image_data.len() {
+                        bail!("Error: source index {} out of bounds (data length {})", src + 3, image_data.len());
+                    }
+
+                    out[dst] = image_data[src];
+                    out[dst + 1] = image_data[src + 1];
+                    out[dst + 2] = image_data[src + 2];
+                    out[dst + 3] = image_data[src + 3];
+                }
+            }
+        }
+        out
+    } else if format == gim::ImageFormat::INDEX8 {
+        if let Some(pal_data) = pal_data {
+            let mut out = vec![0u8; iw * ih * 4];
+            if order == gim::ImageOrder::PSPImage && !args.linear {
+                // read as 16 x 8 tiles and convert to linear output
+                let (tw, th) = tile_size(args, format, (16, 8), iw, ih)?;
+                let tiles_x = iw / tw;
+                let tiles_y = ih / th;
+
+                for ty in 0..tiles_y {
+                    for tx in 0..tiles_x {
+                        let tile_index = ty * tiles_x + tx;
+                        let tile_offset = tile_index * tw * th;
+
+                        for y in 0..th {
+                            for x in 0..tw {
+                                let src = tile_offset + y * tw + x; // palette index
+
+                                // Convert tile coords -> image coords
+                                let px = tx * tw + x;
+                                let py = ty * th + y;
+                                let dst = (py * iw + px) * 4;
+
+                                if src >= image_data.len() {
+                                    vprintln!(args.verbose, "row {}, col {}", y, x);
+                                    bail!("Error: source index {} out of bounds (data length {})", src, image_data.len());
+                                }
+
+                                let pal_offset = (image_data[src] as usize) * 4;
+
+                                out[dst] = pal_data[pal_offset];
+                                out[dst + 1] = pal_data[pal_offset + 1];
+                                out[dst + 2] = pal_data[pal_offset + 2];
+                                out[dst + 3] = pal_data[pal_offset + 3];
+                            }
+                        }
+                    }
+                }
+            } else {
+                //linear image data
+                for y in 0..ih {
+                    for x in 0..iw {
+                        let src = y * iw + x; // palette index
+                        let dst = (y * iw + x) * 4;
+
+                        if src >= image_data.len() {
+                            vprintln!(args.verbose, "row {}, col {}", y, x);
+                            bail!("Error: source index {} out of bounds (data length {})", src, image_data.len());
+                        }
+
+                        let pal_offset = (image_data[src] as usize) * 4;
+
+                        out[dst] = pal_data[pal_offset];
+                        out[dst + 1] = pal_data[pal_offset + 1];
+                        out[dst + 2] = pal_data[pal_offset + 2];
+                        out[dst + 3] = pal_data[pal_offset + 3];
+                    }
+                }
+            }
+            out
+        } else {
+            bail!("Error: GIM Image Format has no understood palette.");
+        }
+    } else if format == gim::ImageFormat::INDEX4 {
+        if let Some(pal_data) = pal_data {
+            let mut out = vec![0u8; iw * ih * 4];
+            if order == gim::ImageOrder::PSPImage && !args.linear {
+                // read as 16 x 8 tiles and convert to linear output
+                let (tw, th) = tile_size(args, format, (32, 8), iw, ih)?;
+                let tiles_x = iw / tw;
+                let tiles_y = ih / th;
+
+                for ty in 0..tiles_y {
+                    for tx in 0..tiles_x {
+                        let tile_index = ty * tiles_x + tx;
+                        let tile_offset = tile_index * tw * th;
+
+                        for y in 0..th {
+                            for x in (0..tw).step_by(2) {
+                                let pixel_index = tile_offset + y * tw + x;
+
+                                // For 4-bit: divide by 2 to get byte position
+                                let src = pixel_index / 2;
+
+                                // Convert tile coords -> image coords
+                                let px = tx * tw + x;
+                                let py = ty * th + y;
+                                let dst = (py * iw + px) * 4;
+
+                                if src >= image_data.len() {
+                                    vprintln!(args.verbose, "row {}, col {}", y, x);
+                                    bail!("Error: source index {} out of bounds (data length {})", src, image_data.len());
+                                }
+
+                                let pal_index0 = ((image_data[src] & 0xF) as usize) * 4;
+                                let pal_index1 = ((image_data[src] >> 4) as usize) * 4;
+
+                                out[dst] = pal_data[pal_index0];
+                                out[dst + 1] = pal_data[pal_index0 + 1];
+                                out[dst + 2] = pal_data[pal_index0 + 2];
+                                out[dst + 3] = pal_data[pal_index0 + 3];
+
+                                out[dst + 4] = pal_data[pal_index1];
+                                out[dst + 5] = pal_data[pal_index1 + 1];
+                                out[dst + 6] = pal_data[pal_index1 + 2];
+                                out[dst + 7] = pal_data[pal_index1 + 3];
+                            }
+                        }
+                    }
+                }
+            } else {
+                //linear image data
+                for y in 0..ih {
+                    let row_src = (y * iw) / 2; // each byte has 2 pixels
+                    let row_dest = y * iw * 4;
+                    for x in 0..(iw / 2) {
+                        let src = row_src + x;
+                        let dst = row_dest + x * 8;
+
+                        if src >= image_data.len() {
+                            vprintln!(args.verbose, "row {}, col {}", y, x * 2);
+                            bail!("Error: source index {} out of bounds (data length {})", src, image_data.len());
+                        }
+
+                        let pal_index0 = ((image_data[src] & 0xF) as usize) * 4;
+                        let pal_index1 = ((image_data[src] >> 4) as usize) * 4;
+
+                        out[dst] = pal_data[pal_index0];
+                        out[dst + 1] = pal_data[pal_index0 + 1];
+                        out[dst + 2] = pal_data[pal_index0 + 2];
+                        out[dst + 3] = pal_data[pal_index0 + 3];
+
+                        out[dst + 4] = pal_data[pal_index1];
+                        out[dst + 5] = pal_data[pal_index1 + 1];
+                        out[dst + 6] = pal_data[pal_index1 + 2];
+                        out[dst + 7] = pal_data[pal_index1 + 3];
+                    }
+                }
+            }
+            out
+        } else {
+            bail!("Error: GIM Image Format has no understood palette.");
+        }
+    } else if format == gim::ImageFormat::INDEX16 {
+        let Some(pal_data) = pal_data else {
+            bail!("Error: GIM Image Format has no understood palette.");
+        };
+        let size = iw * ih * 2;
+        let Some(image_data) = image_data.get(..size) else {
+            bail!("Error: not enough image data for {} x {} at 16 bpp (data length {})", iw, ih, image_data.len());
+        };
+        let indices = if order == gim::ImageOrder::PSPImage && !args.linear {
+            //16 byte wide tiles are 8 pixels at 16 bpp
+            let (tw, th) = tile_size(args, format, (8, 8), iw, ih)?;
+            vprintln!(args.verbose, "Tile dimensions: {} x {}", tw, th);
+            Cow::Owned(gim::unswizzle(image_data, iw * 2, ih, tw * 2, th)?)
+        } else {
+            Cow::Borrowed(image_data)
+        };
+        let entries = pal_data.len() / 4;
+        let mut out = Vec::with_capacity(iw * ih * 4);
+        for (i, index) in indices.chunks_exact(2).enumerate() {
+            let index = u16::from_le_bytes([index[0], index[1]]) as usize;
+            let Some(colour) = pal_data.get(index * 4..index * 4 + 4) else {
+                bail!(
+                    "Error: palette index {} at pixel ({}, {}) is past the end of the {} entry palette",
+                    index,
+                    i % iw,
+                    i / iw,
+                    entries
+                );
+            };
+            out.extend_from_slice(colour);
+        }
+        out
+    } else if format == gim::ImageFormat::RGBA4444 {
+        let size = iw * ih * 2;
+        let Some(image_data) = image_data.get(..size) else {
+            bail!("Error: not enough image data for {} x {} at 16 bpp (data length {})", iw, ih, image_data.len());
+        };
+        let pixels = if order == gim::ImageOrder::PSPImage && !args.linear {
+            //tiles are 16 bytes wide like every format, which is 8 pixels at 16 bpp
+            let (tw, th) = tile_size(args, format, (8, 8), iw, ih)?;
+            vprintln!(args.verbose, "Tile dimensions: {} x {}", tw, th);
+            Cow::Owned(gim::unswizzle(image_data, iw * 2, ih, tw * 2, th)?)
+        } else {
+            Cow::Borrowed(image_data)
+        };
+        //red is in the low bits, each 4 bit channel is expanded to 8 bits by repeating it
+        pixels
+            .chunks_exact(2)
+            .flat_map(|p| {
+                let pix = u16::from_le_bytes([p[0], p[1]]);
+                [0, 4, 8, 12].map(|shift| {
+                    let n = ((pix >> shift) & 0xF) as u8;
+                    (n << 4) | n
+                })
+            })
+            .collect()
+    } else {
+        bail!("Error: GIM Image Format '{}' not supported for conversion.", format);
+    };
+    Ok((out, iw, ih))
+}
+
+/// Picks the tile size for a tiled image: `-x` and `-y` come first, then the `--tile-config` entry for the
+/// format, then the default. Sizes from the config must divide the aligned image dimensions.
+fn tile_size(args: &Args, format: gim::ImageFormat, default: (usize, usize), iw: usize, ih: usize) -> Result<(usize, usize)> {
+    let config = args.tile_config.get(&format).copied();
+    if let Some((tw, th)) = config
+        && ((args.tx == 0 && !iw.is_multiple_of(tw)) || (args.ty == 0 && !ih.is_multiple_of(th)))
+    {
+        bail!(
+            "Error: tile size {} x {} from the tile config does not divide the {} image size {} x {}",
+            tw,
+            th,
+            format,
+            iw,
+            ih
+        );
+    }
+    let (tw, th) = config.unwrap_or(default);
+    Ok((if args.tx > 0 { args.tx } else { tw }, if args.ty > 0 { args.ty } else { th }))
+}
+
+/// Reads a `--tile-config` file, which has a `<format> <width> <height>` line for each format to override.
+/// Blank lines and lines starting with `#` are ignored.
+fn load_tile_config(path: &str) -> Result<HashMap<gim::ImageFormat, (usize, usize)>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read tile config: {}", path))?;
+    let mut config = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_line = || -> Result<(gim::ImageFormat, usize, usize)> {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [format, width, height] = fields[..] else {
+                bail!("expected '<format> <width> <height>'");
+            };
+            let format = format.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+            let (width, height) = (width.parse()?, height.parse()?);
+            if width == 0 || height == 0 {
+                bail!("tile sizes must be greater than 0");
+            }
+            Ok((format, width, height))
+        };
+        let (format, width, height) = parse_line().with_context(|| format!("Invalid tile config {} line {}", path, number + 1))?;
+        config.insert(format, (width, height));
+    }
+    Ok(config)
+}
+
+/// Prints the headers and offsets tables of the image and palette as a single line of JSON for `--json`. Only the
+/// headers are read, so this works for formats that can't be converted too.
+fn print_headers_json(filename: &str, file_data: &[u8]) -> Result<()> {
+    let picture = gim::load_gim_image(file_data).context("Failed to load image")?;
+    let mut value = serde_json::json!({
+        "input": filename,
+        "big_endian": gim::is_big_endian(file_data),
+        "image": header_json(&picture.image_header, &picture.image_offsets),
+        "palette": serde_json::Value::Null,
+    });
+    if let (Some(header), Some(offsets)) = (&picture.palette_header, &picture.palette_offsets) {
+        value["palette"] = header_json(header, offsets);
+        value["palette"]["entries"] = picture.palette_entry_count().into();
+    }
+    println!("{}", value);
+    Ok(())
+}
+
+/// The fields of an image or palette header for `--json`. The format and order are given by name, or as their
+/// number when they are not known.
+fn header_json(header: &gim::GimImageHeader, offsets: &[u32]) -> serde_json::Value {
+    serde_json::json!({
+        "format": header.image_format().map_or(header.format.into(), |format| serde_json::Value::from(format.to_string())),
+        "order": header.image_order().map_or(header.order.into(), |order| serde_json::Value::from(order.to_string())),
+        "width": header.width,
+        "height": header.height,
+        "bpp": header.bpp,
+        "pitch_align": header.pitch_align,
+        "height_align": header.height_align,
+        "dim_count": header.dim_count,
+        "reference": header.reference,
+        "plane_mask": header.plane_mask,
+        "level_type": header.level_type,
+        "level_count": header.level_count,
+        "frame_type": header.frame_type,
+        "frame_count": header.frame_count,
+        "images": header.images,
+        "total": header.total,
+        "offsets": offsets,
+    })
+}
+
+fn dump_chunks(filename: &str, file_data: &[u8]) -> Result<()> {
+    println!("Chunks in {}:", filename);
+    gim::walk_chunks(file_data, |chunk| {
+        println!(
+            "{:indent$}0x{:08X} {} next: 0x{:X} child: 0x{:X} data: 0x{:X}",
+            "",
+            chunk.offset,
+            chunk.kind,
+            chunk.next_offs,
+            chunk.child_offs,
+            chunk.data_offs,
+            indent = chunk.depth * 2
+        );
+        Ok(())
+    })
+}
+
+/// Multiplies the colour channels of every RGBA pixel by its alpha. This is lossy, the original colours of
+/// transparent and partially transparent pixels cannot be recovered from the result.
+fn premultiply_alpha(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Writes the images as a grid of `cell_size` pixel square thumbnails, in input order left to right and top to
+/// bottom, and a text file next to it with the grid position and file name of each.
+fn write_contact_sheet(sheet_path: &std::path::Path, thumbnails: &[(&str, Preview)], args: &Args) -> Result<()> {
+    if thumbnails.is_empty() {
+        bail!("Error: no images were decoded for the contact sheet.");
+    }
+    let cell_size = args.cell_size;
+    let columns = thumbnails.len().isqrt() + usize::from(thumbnails.len().isqrt().pow(2) < thumbnails.len());
+    let rows = thumbnails.len().div_ceil(columns);
+    let sheet_width = columns * cell_size;
+    let mut sheet = vec![0u8; sheet_width * rows * cell_size * 4];
+    let mut index = String::new();
+
+    for (i, (filename, Preview { width, height, rgba })) in thumbnails.iter().enumerate() {
+        let (row, column) = (i / columns, i % columns);
+        index.push_str(&format!("{} {} {}\n", row, column, filename));
+
+        //scaled with nearest neighbour to fit the cell, keeping the aspect ratio, and centred in it
+        let scale = (cell_size as f64 / *width as f64).min(cell_size as f64 / *height as f64);
+        let (thumb_width, thumb_height) = (((*width as f64 * scale) as usize).max(1), ((*height as f64 * scale) as usize).max(1));
+        let left = column * cell_size + (cell_size - thumb_width) / 2;
+        let top = row * cell_size + (cell_size - thumb_height) / 2;
+        for y in 0..thumb_height {
+            let src_y = (y * height / thumb_height).min(height - 1);
+            for x in 0..thumb_width {
+                let src_x = (x * width / thumb_width).min(width - 1);
+                let src = (src_y * width + src_x) * 4;
+                let dst = ((top + y) * sheet_width + left + x) * 4;
+                sheet[dst..dst + 4].copy_from_slice(&rgba[src..src + 4]);
+            }
+        }
+    }
+
+    write_png(sheet_path, sheet_width, rows * cell_size, &sheet, args.colorspace)?;
+    let index_path = suffixed_path(sheet_path, ".txt");
+    std::fs::write(&index_path, index).with_context(|| format!("Failed to write {}", index_path.display()))?;
+    Ok(())
+}
+
+/// Swaps the red and blue channels of every RGBA pixel, for consumers that want BGRA data.
+fn swap_red_blue(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+/// Finds the bounding box `(x, y, width, height)` of the pixels with a non zero alpha, `None` when the image is
+/// fully transparent.
+fn transparent_bounds(rgba: &[u8], width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    if rgba.chunks_exact(4).all(|p| p[3] == 255) {
+        return Some((0, 0, width, height));
+    }
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if rgba[(y * width + x) * 4 + 3] != 0 {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if min_x > max_x || min_y > max_y {
+        return None;
+    }
+    Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// Copies the `width` x `height` region at `(x, y)` out of an RGBA image that is `stride` pixels wide.
+fn crop_rgba(rgba: &[u8], stride: usize, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(width * height * 4);
+    for row in y..y + height {
+        let start = (row * stride + x) * 4;
+        out.extend_from_slice(&rgba[start..start + width * 4]);
+    }
+    out
+}
+
+/// Shrinks an RGBA image by `n`, each pixel of the result being the average of an `n` x `n` block (smaller at
+/// the right and bottom edges). The colours are weighted by alpha so transparent pixels don't darken the edges.
+fn downscale_rgba(rgba: &[u8], width: usize, height: usize, n: usize) -> Vec<u8> {
+    let (out_width, out_height) = (width.div_ceil(n), height.div_ceil(n));
+    let mut out = Vec::with_capacity(out_width * out_height * 4);
+    for by in 0..out_height {
+        for bx in 0..out_width {
+            let (mut sums, mut count) = ([0u64; 4], 0u64);
+            for y in by * n..((by + 1) * n).min(height) {
+                for x in bx * n..((bx + 1) * n).min(width) {
+                    let p = &rgba[(y * width + x) * 4..][..4];
+                    let alpha = p[3] as u64;
+                    for c in 0..3 {
+                        sums[c] += p[c] as u64 * alpha;
+                    }
+                    sums[3] += alpha;
+                    count += 1;
+                }
+            }
+            let alpha = sums[3];
+            let colour = |sum: u64| (sum + alpha / 2).checked_div(alpha).unwrap_or(0) as u8;
+            out.extend_from_slice(&[
+                colour(sums[0]),
+                colour(sums[1]),
+                colour(sums[2]),
+                ((alpha + count / 2) / count) as u8,
+            ]);
+        }
+    }
+    out
+}
+
+/// Shrinks an RGBA image by `n` by keeping the top left pixel of each `n` x `n` block.
+fn downscale_nearest(rgba: &[u8], width: usize, height: usize, n: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(width.div_ceil(n) * height.div_ceil(n) * 4);
+    for y in (0..height).step_by(n) {
+        for x in (0..width).step_by(n) {
+            out.extend_from_slice(&rgba[(y * width + x) * 4..][..4]);
+        }
+    }
+    out
+}
+
+/// Marks the PNG as being in `colorspace`.
+fn set_colorspace<W: Write>(encoder: &mut png::Encoder<W>, colorspace: ColorSpace) {
+    match colorspace {
+        ColorSpace::Srgb => {
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+            //1 / 2.2, the gamma the sRGB chunk stands for, for viewers that only read gAMA
+            encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
+        }
+        ColorSpace::Linear => encoder.set_source_gamma(png::ScaledFloat::new(1.0)),
+    }
+}
+
+fn write_png(output_path: &std::path::Path, width: usize, height: usize, rgba: &[u8], colorspace: ColorSpace) -> Result<()> {
+    let mut ow = std::io::BufWriter::new(std::fs::File::create(output_path).context("Failed to create output file")?);
+
+    let mut encoder = png::Encoder::new(&mut ow, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    set_colorspace(&mut encoder, colorspace);
+    let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+    writer.write_image_data(rgba).context("Failed to write PNG data")?;
+    Ok(())
+}
+
+/// Writes a single plane image as a greyscale PNG, or for the alpha plane a white greyscale and alpha PNG.
+/// The pixels are RGBA as decoded through [`plane_palette`].
+fn write_plane_png(
+    output_path: &std::path::Path,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    alpha: bool,
+    colorspace: ColorSpace,
+) -> Result<()> {
+    let (color, data): (_, Vec<u8>) = if alpha {
+        (png::ColorType::GrayscaleAlpha, rgba.chunks_exact(4).flat_map(|p| [p[0], p[3]]).collect())
+    } else {
+        (png::ColorType::Grayscale, rgba.chunks_exact(4).map(|p| p[0]).collect())
+    };
+
+    let mut ow = std::io::BufWriter::new(std::fs::File::create(output_path).context("Failed to create output file")?);
+    let mut encoder = png::Encoder::new(&mut ow, width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    set_colorspace(&mut encoder, colorspace);
+    let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+    writer.write_image_data(&data).context("Failed to write PNG data")?;
+    Ok(())
+}
+
+/// A palette that turns the 8 bit samples of a single plane into RGBA, a grey ramp for the colour planes and
+/// white with an alpha ramp for the alpha plane.
+fn plane_palette(plane: gim::Plane) -> Vec<u8> {
+    (0..=255)
+        .flat_map(|i| match plane {
+            gim::Plane::Alpha => [255, 255, 255, i],
+            _ => [i, i, i, 255],
+        })
+        .collect()
+}
+
+/// Writes the palette indices as an indexed PNG of `depth` bits per pixel, with the palette entries that fit
+/// in that depth as its PLTE and their alpha as its tRNS.
+fn write_indexed_png(
+    output_path: &std::path::Path,
+    width: usize,
+    height: usize,
+    indices: &[u8],
+    palette: &[u8],
+    depth: u8,
+    colorspace: ColorSpace,
+) -> Result<()> {
+    let entries = (palette.len() / 4).min(1 << depth);
+    if let Some(&index) = indices.iter().find(|&&index| index as usize >= entries) {
+        bail!(
+            "Error: palette index {} cannot be written to a {} bit PNG with {} palette entries",
+            index,
+            depth,
+            entries
+        );
+    }
+
+    let rows: Vec<u8> = if depth == 4 {
+        //two pixels to a byte, the first in the high bits, and every row starts on a new byte
+        indices
+            .chunks_exact(width)
+            .flat_map(|row| row.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)))
+            .collect()
+    } else {
+        indices.to_vec()
+    };
+    let plte: Vec<u8> = palette
+        .chunks_exact(4)
+        .take(entries)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    //tRNS can stop after the last entry that is not fully opaque
+    let alpha: Vec<u8> = palette.chunks_exact(4).take(entries).map(|c| c[3]).collect();
+    let trns_len = alpha.iter().rposition(|&a| a != 255).map_or(0, |i| i + 1);
+
+    let mut ow = std::io::BufWriter::new(std::fs::File::create(output_path).context("Failed to create output file")?);
+    let mut encoder = png::Encoder::new(&mut ow, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(if depth == 4 {
+        png::BitDepth::Four
+    } else {
+        png::BitDepth::Eight
+    });
+    encoder.set_palette(plte);
+    set_colorspace(&mut encoder, colorspace);
+    if trns_len > 0 {
+        encoder.set_trns(alpha[..trns_len].to_vec());
+    }
+    let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+    writer.write_image_data(&rows).context("Failed to write PNG data")?;
+    Ok(())
+}
+
+/// Looks up the colour of every index decoded through the identity palette, see `--index-depth`.
+fn apply_palette(pal_data: &[u8], indices: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(indices.len());
+    for pixel in indices.chunks_exact(4) {
+        let pal_offset = pixel[0] as usize * 4;
+        let Some(colour) = pal_data.get(pal_offset..pal_offset + 4) else {
+            bail!("Error: palette index {} is beyond the {} entries of the palette", pixel[0], pal_data.len() / 4);
+        };
+        out.extend_from_slice(colour);
+    }
+    Ok(out)
+}
+
+/// Writes the frames as a looping animated PNG, each shown for `delay_ms`. The first frame is also the image
+/// that viewers without APNG support show, and a single frame is written as a plain PNG.
+fn write_apng(
+    output_path: &std::path::Path,
+    width: usize,
+    height: usize,
+    frames: &[Vec<u8>],
+    delay_ms: u32,
+    colorspace: ColorSpace,
+) -> Result<()> {
+    let mut ow = std::io::BufWriter::new(std::fs::File::create(output_path).context("Failed to create output file")?);
+
+    let mut encoder = png::Encoder::new(&mut ow, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    set_colorspace(&mut encoder, colorspace);
+    if frames.len() > 1 {
+        encoder
+            .set_animated(frames.len() as u32, 0)
+            .context("Failed to write PNG header")?;
+        encoder
+            .set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)
+            .context("Failed to write PNG header")?;
+        encoder
+            .set_dispose_op(png::DisposeOp::Background)
+            .context("Failed to write PNG header")?;
+    }
+    let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+    for rgba in frames {
+        writer.write_image_data(rgba).context("Failed to write PNG frame")?;
+    }
+    Ok(())
+}
+
+/// Writes the frames as a looping animated GIF. An indexed source reuses its own palette so no colours
+/// are lost, anything else is quantized to 256 colours per frame.
+fn write_gif(
+    output_path: &std::path::Path,
+    width: usize,
+    height: usize,
+    frames: &[Vec<u8>],
+    palette: Option<&[u8]>,
+    delay_ms: u32,
+) -> Result<()> {
+    let width = u16::try_from(width).context("Image is too wide for a GIF")?;
+    let height = u16::try_from(height).context("Image is too tall for a GIF")?;
+    let ow = std::io::BufWriter::new(std::fs::File::create(output_path).context("Failed to create output file")?);
+
+    let global_palette: Vec<u8> = palette
+        .map(|p| p.chunks_exact(4).take(256).flat_map(|c| [c[0], c[1], c[2]]).collect())
+        .unwrap_or_default();
+    let mut encoder = gif::Encoder::new(ow, width, height, &global_palette).context("Failed to write GIF header")?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .context("Failed to write GIF header")?;
+
+    for rgba in frames {
+        let mut frame = match palette.and_then(|p| index_with_palette(p, rgba)) {
+            Some((indices, transparent)) => gif::Frame::from_indexed_pixels(width, height, indices, transparent),
+            None => gif::Frame::from_rgba_speed(width, height, &mut rgba.clone(), 10),
+        };
+        //the delay is in units of 10ms
+        frame.delay = (delay_ms.div_ceil(10)).min(u16::MAX as u32) as u16;
+        frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&frame).context("Failed to write GIF frame")?;
+    }
+    Ok(())
+}
+
+/// Maps RGBA pixels back to indices into the (at most 256 entry) RGBA palette they were decoded with,
+/// along with the first fully transparent entry to use as the GIF transparent index.
+/// Returns `None` if a pixel is not in the palette.
+fn index_with_palette(palette: &[u8], rgba: &[u8]) -> Option<(Vec<u8>, Option<u8>)> {
+    let mut lookup = std::collections::HashMap::new();
+    for (i, entry) in palette.chunks_exact(4).take(256).enumerate() {
+        lookup.entry(entry).or_insert(i as u8);
+    }
+    let transparent = palette.chunks_exact(4).take(256).position(|c| c[3] == 0).map(|i| i as u8);
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|p| match transparent {
+            Some(index) if p[3] == 0 => Some(index),
+            _ => lookup.get(p).copied(),
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Some((indices, transparent))
+}
+
+/// Loads the palette of an indexed image, converted to RGBA in linear order. Returns `None` for formats
+/// that don't use a palette.
+fn load_palette<'a>(
+    picture: &gim::GimPicture<'a>,
+    format: gim::ImageFormat,
+    order: gim::ImageOrder,
+    args: &Args,
+) -> Result<Option<Cow<'a, [u8]>>> {
+    if !matches!(format, gim::ImageFormat::INDEX4 | gim::ImageFormat::INDEX8 | gim::ImageFormat::INDEX16) {
+        return Ok(None);
+    }
+    let (Some(palette), Some(raw_pal_data)) = (&picture.palette_header, picture.palette_data) else {
+        bail!("Error: GIM Image Format has no understood palette.");
+    };
+    //with --clut-swizzle a tiled INDEX8 image is expected to come with a palette in the PSP CLUT order too
+    let auto_swizzle =
+        args.clut_swizzle && format == gim::ImageFormat::INDEX8 && order == gim::ImageOrder::PSPImage && !args.linear;
+    let entries = picture.palette_entry_count().unwrap_or_default();
+    let mut pal_data = prepare_palette(palette, raw_pal_data, entries, auto_swizzle, args)?;
+    //indices past the end of a small CLUT decode as transparent black rather than reading past it, INDEX16
+    //indices are checked as they are decoded instead, as the palette would be padded out to 65536 entries
+    let index_range = 1 << format.bits_per_pixel();
+    if format != gim::ImageFormat::INDEX16 && entries < index_range {
+        vprintln!(args.verbose, "Palette has {} of the {} entries the indices can address", entries, index_range);
+        pal_data.to_mut().resize(index_range * 4, 0);
+    }
+    Ok(Some(pal_data))
+}
+
+/// Returns the entries of the converted palette that the palette header declares, the palette data may be
+/// padded past them.
+fn declared_palette<'a>(picture: &gim::GimPicture, pal_data: &'a [u8]) -> Result<&'a [u8]> {
+    let entries = picture
+        .palette_entry_count()
+        .context("Palette header not found")?
+        .min(pal_data.len() / 4);
+    Ok(&pal_data[..entries * 4])
+}
+
+/// Writes the palette for `--palette-export` next to the output, as `output_path` with the extension of the format.
+/// Neither format has alpha, so only the colours are written.
+fn export_palette(output_path: &std::path::Path, pal_data: &[u8], export: PaletteExport, args: &Args) -> Result<()> {
+    let (extension, data) = match export {
+        PaletteExport::Act => {
+            //always 256 entries, unused ones are black
+            let mut data = vec![0u8; 256 * 3];
+            for (entry, colour) in data.chunks_exact_mut(3).zip(pal_data.chunks_exact(4)) {
+                entry.copy_from_slice(&colour[..3]);
+            }
+            ("act", data)
+        }
+        PaletteExport::Gpl => {
+            let name = output_path.file_name().unwrap_or_default().to_string_lossy();
+            let mut text = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+            for (i, colour) in pal_data.chunks_exact(4).enumerate() {
+                text.push_str(&format!("{:3} {:3} {:3}\tIndex {}\n", colour[0], colour[1], colour[2], i));
+            }
+            ("gpl", text.into_bytes())
+        }
+    };
+    let path = suffixed_path(output_path, &format!(".{}", extension));
+    std::fs::write(&path, data).with_context(|| format!("Failed to write palette file: {}", path.display()))?;
+    vprintln!(!args.quiet, "Extracted palette file: {} ({} entries)", path.display(), pal_data.len() / 4);
+    Ok(())
+}
+
+/// Writes the palette as a PNG with a 16 x 16 pixel cell per entry, 16 entries to a row.
+fn write_palette_swatch(output_path: &std::path::Path, pal_data: &[u8], colorspace: ColorSpace) -> Result<()> {
+    const CELL: usize = 16;
+    const COLUMNS: usize = 16;
+    let entries = pal_data.len() / 4;
+    if entries == 0 {
+        bail!("Error: GIM Palette has no entries.");
+    }
+    let columns = entries.min(COLUMNS);
+    let rows = entries.div_ceil(COLUMNS);
+    let (width, height) = (columns * CELL, rows * CELL);
+
+    //entries past the end of the last row are left transparent
+    let mut out = vec![0u8; width * height * 4];
+    for (i, entry) in pal_data.chunks_exact(4).enumerate() {
+        let (cell_x, cell_y) = ((i % COLUMNS) * CELL, (i / COLUMNS) * CELL);
+        for y in cell_y..cell_y + CELL {
+            for x in cell_x..cell_x + CELL {
+                let dst = (y * width + x) * 4;
+                out[dst..dst + 4].copy_from_slice(entry);
+            }
+        }
+    }
+
+    write_png(output_path, width, height, &out, colorspace)
+}
+
+/// Converts the first `entries` entries of the palette to RGBA and puts them into linear order. Unless overridden
+/// by `--palette-order`, the CLUT swizzle is undone when `auto_swizzle` is set and the palette header reports PSP order.
+fn prepare_palette<'a>(
+    palette_header: &gim::GimImageHeader,
+    palette_data: &'a [u8],
+    entries: usize,
+    auto_swizzle: bool,
+    args: &Args,
+) -> Result<Cow<'a, [u8]>> {
+    let mut pal_data = convert_palette_for_png(palette_header, palette_data, entries)?;
+    let is_16bit = matches!(
+        palette_header.image_format(),
+        Some(gim::ImageFormat::RGBA5650 | gim::ImageFormat::RGBA5551 | gim::ImageFormat::RGBA4444)
+    );
+    if is_16bit && args.palette_channel_order == ChannelOrder::Bgra {
+        vprintln!(args.verbose, "Swapping red and blue in palette");
+        for entry in pal_data.to_mut().chunks_exact_mut(4) {
+            entry.swap(0, 2);
+        }
+    }
+    let swizzled = match args.palette_order {
+        Some(PaletteOrder::Psp) => true,
+        Some(PaletteOrder::Linear) => false,
+        None => auto_swizzle && palette_header.image_order() == Some(gim::ImageOrder::PSPImage),
+    };
+    if swizzled {
+        vprintln!(args.verbose, "Reordering palette from PSP CLUT order");
+        pal_data = Cow::Owned(unswizzle_clut(&pal_data));
+    }
+    if let Some(ColorKey(key)) = args.color_key
+        && pal_data.chunks_exact(4).any(|entry| entry[..3] == key)
+    {
+        let mut keyed = 0;
+        for entry in pal_data.to_mut().chunks_exact_mut(4).filter(|entry| entry[..3] == key) {
+            entry[3] = 0;
+            keyed += 1;
+        }
+        vprintln!(args.verbose, "Made {} palette entries matching the colour key transparent", keyed);
+    }
+    if let Some(index) = args.transparent_index {
+        let entries = pal_data.len() / 4;
+        if index < entries {
+            pal_data.to_mut()[index * 4 + 3] = 0;
+        } else {
+            warn(args, format!("--transparent-index {} is past the end of the {} entry palette", index, entries))?;
+        }
+    }
+    Ok(pal_data)
+}
+
+/// Reorders a converted RGBA palette from the PSP CLUT layout into linear order.
+///
+/// Within every block of 32 entries the hardware layout has the second and third groups of 8 entries
+/// swapped (entries 8-15 are stored where 16-23 belong and vice versa), which is the same as swapping
+/// bits 3 and 4 of the palette index. A trailing partial block is left as is.
+fn unswizzle_clut(pal_data: &[u8]) -> Vec<u8> {
+    let mut out = pal_data.to_vec();
+    let blocks = pal_data.len() / (32 * 4);
+    for block in 0..blocks {
+        for i in 0..32 {
+            let swapped = (i & !0x18) | ((i & 0x08) << 1) | ((i & 0x10) >> 1);
+            let src = (block * 32 + swapped) * 4;
+            let dst = (block * 32 + i) * 4;
+            out[dst..dst + 4].copy_from_slice(&pal_data[src..src + 4]);
+        }
+    }
+    out
+}
+
+fn convert_palette_for_png<'a>(
+    palette_header: &gim::GimImageHeader,
+    palette_data: &'a [u8],
+    entries: usize,
+) -> Result<Cow<'a, [u8]>> {
+    let format = palette_header.image_format().context("Failed to get palette image format")?;
+    if !PALETTE_FORMATS.contains(&format) {
+        bail!("Error: GIM Palette format '{}' not supported for conversion.", format);
+    }
+
+    match format {
+        gim::ImageFormat::RGBA8888 => Ok(Cow::Borrowed(&palette_data[..entries * 4])),
+        gim::ImageFormat::RGBA5551 => {
+            let mut out = vec![0u8; entries * 4];
+
+            for i in 0..entries {
+                let src_offset = i * 2;
+                let dst_offset = i * 4;
+                let pix_low = palette_data[src_offset];
+                let pix_high = palette_data[src_offset + 1];
+                let pix = ((pix_high as u16) << 8) | (pix_low as u16);
+
+                let b = (((pix >> 10) & 0x1F) << 3) as u8;
+                let g = (((pix >> 5) & 0x1F) << 3) as u8;
+                let r = ((pix & 0x1F) << 3) as u8;
+                let a = if (pix & 0x8000) != 0 { 255 } else { 0 };
+
+                out[dst_offset] = r;
+                out[dst_offset + 1] = g;
+                out[dst_offset + 2] = b;
+                out[dst_offset + 3] = a;
+            }
+            Ok(Cow::Owned(out))
+        }
+        gim::ImageFormat::RGBA5650 => {
+            let mut out = vec![0u8; entries * 4];
+
+            for i in 0..entries {
+                let src_offset = i * 2;
+                let dst_offset = i * 4;
+                let pix = u16::from_le_bytes([palette_data[src_offset], palette_data[src_offset + 1]]);
+
+                //expand to 8 bits by replicating the high bits into the low bits, there is no alpha so it is opaque
+                let r5 = (pix & 0x1F) as u8;
+                let g6 = ((pix >> 5) & 0x3F) as u8;
+                let b5 = ((pix >> 11) & 0x1F) as u8;
+
+                out[dst_offset] = (r5 << 3) | (r5 >> 2);
+                out[dst_offset + 1] = (g6 << 2) | (g6 >> 4);
+                out[dst_offset + 2] = (b5 << 3) | (b5 >> 2);
+                out[dst_offset + 3] = 255;
+            }
+            Ok(Cow::Owned(out))
+        }
+        gim::ImageFormat::RGBA4444 => {
+            let mut out = vec![0u8; entries * 4];
+
+            for i in 0..entries {
+                let src_offset = i * 2;
+                let dst_offset = i * 4;
+                let pix = u16::from_le_bytes([palette_data[src_offset], palette_data[src_offset + 1]]);
+
+                //red is in the low bits, each 4 bit channel is expanded to 8 bits by repeating it
+                for channel in 0..4 {
+                    let n = ((pix >> (channel * 4)) & 0xF) as u8;
+                    out[dst_offset + channel] = (n << 4) | n;
+                }
+            }
+            Ok(Cow::Owned(out))
+        }
+        _ => {
+            bail!("Error: GIM Palette format '{}' not supported for conversion.", format);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_rgba8888_keeps_its_pixels() {
+        let header = gim::GimImageHeader::new(gim::ImageFormat::RGBA8888, gim::ImageOrder::Normal, 16, 8);
+        let pixels: Vec<u8> = (0..16 * 8 * 4).map(|i| (i % 251) as u8 + 1).collect();
+        let data = gim::write_gim((header, &pixels), None);
+        let picture = gim::load_gim_image(&data).unwrap();
+        let args = parse_args(["test.gim"]).unwrap();
+        let (out, iw, ih) = decode_image(&picture, gim::ImageFormat::RGBA8888, picture.image_data, None, 16, 8, &args).unwrap();
+        assert_eq!((iw, ih), (16, 8));
+        assert!(out.iter().all(|&byte| byte != 0));
+        assert_eq!(out, pixels);
+    }
+
+    /// A 32 entry RGBA palette whose entry `i` has the red value `order[i]`.
+    fn palette_with_order(order: &[u8]) -> Vec<u8> {
+        order.iter().flat_map(|&i| [i, 0, 0, 255]).collect()
+    }
+
+    /// The first 32 entries in the PSP CLUT order, the second and third groups of 8 swapped.
+    const SWIZZLED_ORDER: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 16, 17, 18, 19, 20, 21, 22, 23, 8, 9, 10, 11, 12, 13, 14, 15, 24, 25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    #[test]
+    fn unswizzle_clut_restores_linear_order() {
+        let linear: Vec<u8> = (0..32).collect();
+        assert_eq!(unswizzle_clut(&palette_with_order(&SWIZZLED_ORDER)), palette_with_order(&linear));
+    }
+
+    /// Loads the palette of a tiled INDEX8 GIM whose palette header reports PSP order, with the given arguments.
+    fn load_tiled_palette(args: &[&str], palette: &[u8]) -> Vec<u8> {
+        let image = gim::GimImageHeader::new(gim::ImageFormat::INDEX8, gim::ImageOrder::PSPImage, 16, 8);
+        let palette_header = gim::GimImageHeader::new(gim::ImageFormat::RGBA8888, gim::ImageOrder::PSPImage, 32, 1);
+        let data = gim::write_gim((image, &[0u8; 16 * 8]), Some((palette_header, palette)));
+        let picture = gim::load_gim_image(&data).unwrap();
+        let args = parse_args(args.iter().chain(&["test.gim"])).unwrap();
+        let pal_data = load_palette(&picture, gim::ImageFormat::INDEX8, gim::ImageOrder::PSPImage, &args)
+            .unwrap()
+            .unwrap();
+        pal_data[..32 * 4].to_vec()
+    }
+
+    #[test]
+    fn clut_swizzle_is_opt_in() {
+        let swizzled = palette_with_order(&SWIZZLED_ORDER);
+        let linear = palette_with_order(&(0..32).collect::<Vec<u8>>());
+        assert_eq!(load_tiled_palette(&[], &swizzled), swizzled);
+        assert_eq!(load_tiled_palette(&["--clut-swizzle"], &swizzled), linear);
+        assert_eq!(load_tiled_palette(&["--palette-order", "psp"], &swizzled), linear);
+        assert_eq!(load_tiled_palette(&["--clut-swizzle", "--palette-order", "linear"], &swizzled), swizzled);
+    }
+
+    /// Decodes an indexed image of `width` x `height` with an RGBA8888 palette of `palette.len() / 4` entries, through
+    /// the same palette loading and decoding as a converted file.
+    fn decode_indexed(
+        format: gim::ImageFormat,
+        order: gim::ImageOrder,
+        (width, height): (u16, u16),
+        indices: &[u8],
+        palette: &[u8],
+        args: &[&str],
+    ) -> Vec<u8> {
+        let image = gim::GimImageHeader::new(format, order, width, height);
+        let entries = (palette.len() / 4) as u16;
+        let palette_header = gim::GimImageHeader::new(gim::ImageFormat::RGBA8888, gim::ImageOrder::Normal, entries, 1);
+        let data = gim::write_gim((image, indices), Some((palette_header, palette)));
+        let picture = gim::load_gim_image(&data).unwrap();
+        let args = parse_args(args.iter().chain(&["test.gim"])).unwrap();
+        let pal_data = load_palette(&picture, format, order, &args).unwrap().unwrap();
+        let (out, iw, ih) =
+            decode_image(&picture, format, picture.image_data, Some(&pal_data), width as usize, height as usize, &args).unwrap();
+        assert_eq!((iw, ih), (width as usize, height as usize));
+        out
+    }
+
+    /// A palette of `entries` opaque entries whose entry `i` has the red value `i` and the green value `255 - i`.
+    fn ramp_palette(entries: usize) -> Vec<u8> {
+        (0..entries).flat_map(|i| [i as u8, 255 - i as u8, 0, 255]).collect()
+    }
+
+    #[test]
+    fn short_palette_decodes_indices_past_it_as_transparent() {
+        let indices: Vec<u8> = (0..16 * 8).map(|i| (i * 2) as u8).collect();
+        let out = decode_indexed(gim::ImageFormat::INDEX8, gim::ImageOrder::Normal, (16, 8), &indices, &ramp_palette(16), &[]);
+        for (&index, pixel) in indices.iter().zip(out.chunks_exact(4)) {
+            let expected = if index < 16 { [index, 255 - index, 0, 255] } else { [0; 4] };
+            assert_eq!(pixel, expected, "index {}", index);
+        }
+    }
+
+    #[test]
+    fn full_palette_decodes_every_index() {
+        let indices: Vec<u8> = (0..=255).collect();
+        let out = decode_indexed(gim::ImageFormat::INDEX8, gim::ImageOrder::Normal, (16, 16), &indices, &ramp_palette(256), &[]);
+        assert_eq!(out, ramp_palette(256));
+    }
+
+    #[test]
+    fn index4_looks_up_each_nibble_low_first() {
+        //a stride of 7 gives the bytes a spread of different low and high nibbles
+        let indices: Vec<u8> = (0..16 * 8).map(|i| ((i * 7) % 256) as u8).collect();
+        let palette = ramp_palette(16);
+        let out = decode_indexed(gim::ImageFormat::INDEX4, gim::ImageOrder::Normal, (32, 8), &indices, &palette, &[]);
+        let expected: Vec<u8> = indices
+            .iter()
+            .flat_map(|&byte| [byte & 0xF, byte >> 4])
+            .flat_map(|index| palette[index as usize * 4..][..4].to_vec())
+            .collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn tiled_index4_matches_linear() {
+        //two tiles across and two down, each tile 32 pixels (16 bytes) by 8 rows
+        let (width, height) = (64u16, 16u16);
+        let pitch = width as usize / 2;
+        let linear: Vec<u8> = (0..pitch * height as usize).map(|i| (i * 37 + i / 7) as u8).collect();
+        let tiled = gim::swizzle(&linear, pitch, height as usize, 16, 8).unwrap();
+        let palette = ramp_palette(16);
+        let expected = decode_indexed(gim::ImageFormat::INDEX4, gim::ImageOrder::Normal, (width, height), &linear, &palette, &[]);
+        let out = decode_indexed(gim::ImageFormat::INDEX4, gim::ImageOrder::PSPImage, (width, height), &tiled, &palette, &[]);
+        assert_eq!(out, expected);
+    }
+
+    /// A PSP DXT colour block with every texel using the first colour, pure red.
+    const RED_COLOUR_BLOCK: [u8; 8] = [0, 0, 0, 0, 0x1F, 0, 0, 0];
+
+    /// Packs the 3 bit DXT5 alpha index of each texel and the two endpoints into the PSP alpha block layout.
+    fn dxt5_alpha_block(indices: [u8; 16], a0: u8, a1: u8) -> [u8; 8] {
+        let bits = indices
+            .iter()
+            .enumerate()
+            .fold(0u64, |bits, (i, &index)| bits | (index as u64) << (i * 3));
+        let bytes = bits.to_le_bytes();
+        [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], a0, a1]
+    }
+
+    #[test]
+    fn dxt3_decodes_explicit_alpha() {
+        //texel i has the 4 bit alpha i, which expands to i * 17
+        let alpha = (0..16u64).fold(0u64, |bits, i| bits | i << (i * 4));
+        let block = [RED_COLOUR_BLOCK, alpha.to_le_bytes()].concat();
+        let args = parse_args(["test.gim"]).unwrap();
+        let out = decode_dxt(&block, gim::ImageFormat::DXT3, gim::ImageOrder::Normal, 4, 4, &args).unwrap();
+        let expected: Vec<u8> = (0..16).flat_map(|i| [255, 0, 0, i * 17]).collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn dxt5_interpolates_six_steps_when_the_first_endpoint_is_greater() {
+        let indices = std::array::from_fn(|i| (i % 8) as u8);
+        let alpha = decode_dxt5_alpha(&dxt5_alpha_block(indices, 255, 0));
+        let steps = [255, 0, 218, 182, 145, 109, 72, 36];
+        assert_eq!(alpha, std::array::from_fn(|i| steps[i % 8]));
+    }
+
+    #[test]
+    fn dxt5_interpolates_four_steps_and_the_extremes_otherwise() {
+        let indices = std::array::from_fn(|i| (i % 8) as u8);
+        let alpha = decode_dxt5_alpha(&dxt5_alpha_block(indices, 40, 240));
+        let steps = [40, 240, 80, 120, 160, 200, 0, 255];
+        assert_eq!(alpha, std::array::from_fn(|i| steps[i % 8]));
+    }
+
+    #[test]
+    fn dxt5_block_decodes_colour_and_alpha() {
+        let indices = std::array::from_fn(|i| (i % 8) as u8);
+        let block = [RED_COLOUR_BLOCK, dxt5_alpha_block(indices, 255, 0)].concat();
+        let args = parse_args(["test.gim"]).unwrap();
+        let out = decode_dxt(&block, gim::ImageFormat::DXT5, gim::ImageOrder::Normal, 4, 4, &args).unwrap();
+        let steps = [255, 0, 218, 182, 145, 109, 72, 36];
+        let expected: Vec<u8> = (0..16).flat_map(|i| [255, 0, 0, steps[i % 8]]).collect();
+        assert_eq!(out, expected);
+        let ext = decode_dxt(&block, gim::ImageFormat::DXT5EXT, gim::ImageOrder::Normal, 4, 4, &args).unwrap();
+        assert_eq!(ext, expected);
+    }
+
+    #[test]
+    fn dxt1_blocks_in_psp_order_are_unswizzled() {
+        //16 x 32 pixels is 4 x 8 blocks, two tiles of 2 x 8 blocks; block i is solid with the red value i
+        let (blocks_x, blocks_y) = (4, 8);
+        let linear: Vec<u8> = (0..blocks_x * blocks_y)
+            .flat_map(|i| [0, 0, 0, 0, i as u8, 0, 0, 0])
+            .collect();
+        let swizzled = gim::swizzle(&linear, blocks_x * 8, blocks_y, 16, 8).unwrap();
+        assert_ne!(swizzled, linear);
+        let args = parse_args(["test.gim"]).unwrap();
+        let blocks = dxt_linear_blocks(&swizzled, gim::ImageFormat::DXT1, 8, blocks_x, blocks_y, gim::ImageOrder::PSPImage, &args);
+        assert_eq!(blocks.unwrap(), linear);
+
+        let out = decode_dxt(&swizzled, gim::ImageFormat::DXT1, gim::ImageOrder::PSPImage, 16, 32, &args).unwrap();
+        for (i, pixel) in out.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 16, i / 16);
+            let block = (y / 4 * blocks_x + x / 4) as u8;
+            assert_eq!(pixel, [(block << 3) | (block >> 2), 0, 0, 255], "pixel ({}, {})", x, y);
+        }
+        let linear_args = parse_args(["--linear", "test.gim"]).unwrap();
+        let left = decode_dxt(&swizzled, gim::ImageFormat::DXT1, gim::ImageOrder::PSPImage, 16, 32, &linear_args).unwrap();
+        assert_ne!(left, out);
+    }
+
+    #[test]
+    fn rgba4444_expands_each_channel() {
+        //red is in the low bits: 0xF048 is red 8, green 4, blue 0 and alpha 15
+        let known: [(u16, [u8; 4]); 4] = [
+            (0xF048, [0x88, 0x44, 0x00, 0xFF]),
+            (0x0000, [0, 0, 0, 0]),
+            (0x7FFF, [0xFF, 0xFF, 0xFF, 0x77]),
+            (0x1234, [0x44, 0x33, 0x22, 0x11]),
+        ];
+        let (width, height) = (16usize, 8usize);
+        //each row starts one further along the known pixels, so the tiled layout differs from the linear one
+        let known_index = |i: usize| (i + i / width) % known.len();
+        let linear: Vec<u8> = (0..width * height)
+            .flat_map(|i| known[known_index(i)].0.to_le_bytes())
+            .collect();
+        let expected: Vec<u8> = (0..width * height).flat_map(|i| known[known_index(i)].1).collect();
+        let args = parse_args(["test.gim"]).unwrap();
+
+        let header = gim::GimImageHeader::new(gim::ImageFormat::RGBA4444, gim::ImageOrder::Normal, width as u16, height as u16);
+        let data = gim::write_gim((header, &linear), None);
+        let picture = gim::load_gim_image(&data).unwrap();
+        let (out, _, _) =
+            decode_image(&picture, gim::ImageFormat::RGBA4444, picture.image_data, None, width, height, &args).unwrap();
+        assert_eq!(out, expected);
+
+        //8 pixels to a 16 byte wide tile
+        let tiled = gim::swizzle(&linear, width * 2, height, 16, 8).unwrap();
+        assert_ne!(tiled, linear);
+        let header = gim::GimImageHeader::new(gim::ImageFormat::RGBA4444, gim::ImageOrder::PSPImage, width as u16, height as u16);
+        let data = gim::write_gim((header, &tiled), None);
+        let picture = gim::load_gim_image(&data).unwrap();
+        let (out, _, _) =
+            decode_image(&picture, gim::ImageFormat::RGBA4444, picture.image_data, None, width, height, &args).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn one_dimensional_index8_gradient_is_a_single_linear_row() {
+        //a lookup table that says it is tiled and still has the height padding of 7 rows after it in the file
+        let mut indices: Vec<u8> = (0..=255).collect();
+        indices.resize(256 * 8, 0xFF);
+        let image = gim::GimImageHeader::new(gim::ImageFormat::INDEX8, gim::ImageOrder::PSPImage, 256, 1);
+        let palette = ramp_palette(256);
+        let palette_header = gim::GimImageHeader::new(gim::ImageFormat::RGBA8888, gim::ImageOrder::Normal, 256, 1);
+        let data = gim::write_gim((image, &indices), Some((palette_header, &palette)));
+        let picture = gim::load_gim_image(&data).unwrap();
+        assert!(picture.is_one_dimensional());
+        assert_eq!(picture.aligned_dimensions(), (256, 1));
+
+        let args = parse_args(["--strict", "test.gim"]).unwrap();
+        let pal_data = load_palette(&picture, gim::ImageFormat::INDEX8, gim::ImageOrder::PSPImage, &args)
+            .unwrap()
+            .unwrap();
+        let (out, iw, ih) =
+            decode_image(&picture, gim::ImageFormat::INDEX8, picture.image_data, Some(&pal_data), 256, 1, &args).unwrap();
+        assert_eq!((iw, ih), (256, 1));
+        assert_eq!(out, palette);
+    }
+
+    /// A `width` x `height` frame, transparent apart from opaque pixels at `points`.
+    fn frame_with_points(width: usize, height: usize, points: &[(usize, usize)]) -> Vec<u8> {
+        let mut rgba = vec![0u8; width * height * 4];
+        for &(x, y) in points {
+            rgba[(y * width + x) * 4..][..4].copy_from_slice(&[255; 4]);
+        }
+        rgba
+    }
+
+    #[test]
+    fn trim_bounds_skip_fully_transparent_frames() {
+        let args = parse_args(["--trim-transparent", "--quiet", "--strict", "test.gim"]).unwrap();
+        let frames = [
+            frame_with_points(16, 8, &[(2, 1), (5, 3)]),
+            frame_with_points(16, 8, &[]),
+            frame_with_points(16, 8, &[(9, 2)]),
+        ];
+        assert_eq!(trim_bounds(&frames, 16, 8, "test.gim", &args).unwrap(), Some((2, 1, 8, 3)));
+        assert!(trim_bounds(&frames[1..2], 16, 8, "test.gim", &args).is_err());
+        assert!(trim_bounds(&[vec![255; 16 * 8 * 4]], 16, 8, "test.gim", &args).is_err());
+    }
+
+    #[test]
+    fn image_suffix_names_levels_and_frames() {
+        assert_eq!(image_suffix(0, 1, 0, 1), "");
+        assert_eq!(image_suffix(2, 3, 0, 1), "_mip2");
+        assert_eq!(image_suffix(0, 1, 4, 5), "_f4");
+        assert_eq!(image_suffix(1, 2, 3, 4), "_L1_F3");
+    }
+}
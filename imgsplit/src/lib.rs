@@ -0,0 +1,444 @@
+use std::{
+    ffi::OsString,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow};
+use bytemuck::{Pod, Zeroable};
+use common::{
+    args::{self, Help},
+    errors::{self, ErrorFormat, ErrorReport},
+    vprintln,
+};
+use lexopt::{Arg, ValueExt};
+
+struct Args {
+    input_path: String,
+    embedded_toc: Option<u64>,
+    output_dir: Option<String>,
+    verbose: bool,
+    quiet: bool,
+    mtime: Option<i64>,
+    limit: Option<usize>,
+    continue_on_error: bool,
+    zero_fill: bool,
+    sort: SortKey,
+    error_format: ErrorFormat,
+    hexdump_on_error: bool,
+}
+
+/// The order files are extracted and reported in, from `--sort`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Index,
+    /// Largest first.
+    Size,
+    Name,
+    /// By file extension.
+    Type,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "index" => Ok(SortKey::Index),
+            "size" => Ok(SortKey::Size),
+            "name" => Ok(SortKey::Name),
+            "type" => Ok(SortKey::Type),
+            _ => Err(format!("unknown sort order '{}', expected index, size, name or type", s)),
+        }
+    }
+}
+
+const HELP: Help = Help {
+    name: "imgsplit",
+    version: env!("CARGO_PKG_VERSION"),
+    usage: "[options] <path>",
+    description: &["Extracts the files in <path>/PSXCD.IMG using the PSXCDNAM.BIN and PSXCDLOC.BIN cache files."],
+    options: &[
+        ("--continue-on-error", "Report files that fail to extract and carry on with the next one"),
+        ("--embedded-toc <block>", "Read the cache tables from <block> of PSXCD.IMG instead"),
+        (
+            "--mtime <seconds>",
+            "Set the modification time of every extracted file (seconds since the Unix epoch)",
+        ),
+        (
+            "--sort <key>",
+            "Extract files by 'index' (default), 'size' (largest first), 'name' or 'type' (extension)",
+        ),
+        (
+            "--zero-fill",
+            "Write blocks that can't be read, or that are past the end of PSXCD.IMG, as zeros instead of failing or skipping the file",
+        ),
+    ],
+    notes: &[],
+    short_output: true,
+    threads: false,
+};
+
+fn parse_args<I>(args: I) -> Result<Args, lexopt::Error>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let mut embedded_toc = None;
+    let mut mtime = None;
+    let mut continue_on_error = false;
+    let mut zero_fill = false;
+    let mut sort = SortKey::Index;
+    let common = args::parse(args, &HELP, |arg, parser| {
+        match arg {
+            Arg::Long("embedded-toc") => {
+                embedded_toc = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("mtime") => {
+                mtime = Some(parser.value()?.parse()?);
+            }
+            Arg::Long("continue-on-error") => {
+                continue_on_error = true;
+            }
+            Arg::Long("zero-fill") => {
+                zero_fill = true;
+            }
+            Arg::Long("sort") => {
+                sort = parser.value()?.parse()?;
+            }
+            _ => return Err(arg.unexpected()),
+        }
+        Ok(())
+    })?;
+
+    let mut files = common.files.into_iter();
+    let Some(input_path) = files.next() else {
+        eprintln!("Error: No input path specified.");
+        std::process::exit(1);
+    };
+    if let Some(extra) = files.next() {
+        return Err(format!("only one input path can be given, found '{}' as well", extra).into());
+    }
+
+    Ok(Args {
+        input_path,
+        embedded_toc,
+        output_dir: common.output,
+        verbose: common.verbose,
+        quiet: common.quiet,
+        mtime,
+        limit: common.limit,
+        continue_on_error,
+        zero_fill,
+        sort,
+        error_format: common.error_format,
+        hexdump_on_error: common.hexdump_on_error,
+    })
+}
+
+/// Runs imgsplit with the given command line arguments (not including the program name).
+pub fn run<I>(args: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let args = parse_args(args).map_err(|e| anyhow!("Failed to parse command line: {}", e))?;
+    let file_name = Path::new(&args.input_path).join("PSXCD.IMG");
+    let mut file = std::fs::File::open(&file_name).with_context(|| format!("Failed to open file: {}", file_name.display()))?;
+
+    let cache = match args.embedded_toc {
+        Some(block) => {
+            vprintln!(args.verbose, "Reading embedded cache tables from block {}", block);
+            load_embedded_cd_cache(&mut file, block)?
+        }
+        None => {
+            vprintln!(args.verbose, "Reading cache files from {}", args.input_path);
+            load_cd_cache(&args.input_path)?
+        }
+    };
+
+    let output_dir = Path::new(args.output_dir.as_deref().unwrap_or("."));
+    std::fs::create_dir_all(output_dir).with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+
+    let image_size = file.metadata()?.len();
+    let mut files = Vec::new();
+    for (i, name) in cache.names().iter().enumerate() {
+        if name.name[0] == 0 {
+            break;
+        }
+        let filename = String::from_utf8_lossy(&name.name);
+        let Some(loc) = cache.locs().get(i) else {
+            args.error_format.print(
+                format_args!("Error: no loc entry for file {}, the loc cache is too short", i),
+                ErrorReport {
+                    file: filename.trim_end_matches('\0'),
+                    kind: "missing_loc",
+                    message: "no loc entry for the file, the loc cache is too short",
+                    offset: None,
+                },
+            );
+            break;
+        };
+        files.push((i, filename.trim_end_matches('\0').to_string(), loc));
+    }
+    //the sort is stable so ties stay in index order
+    match args.sort {
+        SortKey::Index => {}
+        SortKey::Size => files.sort_by_key(|(_, _, loc)| std::cmp::Reverse(loc.file_size)),
+        SortKey::Name => files.sort_by(|(_, a, _), (_, b, _)| a.cmp(b)),
+        SortKey::Type => {
+            files.sort_by_cached_key(|(_, filename, _)| Path::new(filename).extension().map(|ext| ext.to_ascii_lowercase()))
+        }
+    }
+
+    let mut failed = 0;
+    let mut skipped = 0;
+    for (count, (i, filename, loc)) in files.into_iter().enumerate() {
+        if args.limit.is_some_and(|limit| count == limit) {
+            vprintln!(args.verbose, "Stopping after {} files (--limit)", count);
+            break;
+        }
+        vprintln!(
+            !args.quiet,
+            "File {}: {} (start block: {}, num blocks: {}, size: {})",
+            i,
+            filename,
+            loc.start_block,
+            loc.num_blocks,
+            loc.file_size
+        );
+
+        if let Err(e) = validate_loc(loc, image_size, args.zero_fill) {
+            args.error_format.print(
+                format_args!("Error: skipping file {}: {}", i, e),
+                ErrorReport {
+                    file: &filename,
+                    kind: "invalid_loc",
+                    message: &e.to_string(),
+                    offset: Some(loc.start_block as u64 * 0x800),
+                },
+            );
+            if args.hexdump_on_error {
+                let loc_offset = i * std::mem::size_of::<CdLoc>();
+                eprint!("Loc table around entry {}:\n{}", i, errors::hexdump(&cache.loc_file_data, loc_offset, 0));
+            }
+            skipped += 1;
+            continue;
+        }
+
+        let output_path = output_dir.join(&filename);
+        if let Err(e) = extract_file(&mut file, loc, &output_path, &args) {
+            if !args.continue_on_error {
+                return Err(e.context(format!("Failed to extract file {}", i)));
+            }
+            args.error_format.print(
+                format_args!("Error: failed to extract file {}: {:#}", i, e),
+                ErrorReport {
+                    file: &filename,
+                    kind: "extract",
+                    message: &format!("{:#}", e),
+                    offset: Some(loc.start_block as u64 * 0x800),
+                },
+            );
+            failed += 1;
+        }
+    }
+    check_failures(failed, skipped)
+}
+
+/// Fails the run when any file could not be extracted, whether it failed or was skipped for an invalid loc.
+fn check_failures(failed: usize, skipped: usize) -> Result<()> {
+    match (failed, skipped) {
+        (0, 0) => Ok(()),
+        (failed, 0) => Err(anyhow!("{} files could not be extracted", failed)),
+        (0, skipped) => Err(anyhow!("{} files were skipped for invalid locs", skipped)),
+        (failed, skipped) => {
+            Err(anyhow!("{} files could not be extracted, {} more were skipped for invalid locs", failed, skipped))
+        }
+    }
+}
+
+/// Copies the blocks of one file out of PSXCD.IMG to `output_path`.
+fn extract_file(file: &mut std::fs::File, loc: &CdLoc, output_path: &Path, args: &Args) -> Result<()> {
+    let start = (loc.start_block as u64) * 0x800;
+    let mut buffer = vec![0u8; (loc.num_blocks as usize) * 0x800];
+    if args.zero_fill {
+        //read a block at a time so a bad sector only loses its own block
+        let mut unreadable = 0;
+        for (block, data) in buffer.chunks_exact_mut(0x800).enumerate() {
+            let offset = start + block as u64 * 0x800;
+            if file
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(data))
+                .is_err()
+            {
+                data.fill(0);
+                unreadable += 1;
+            }
+        }
+        if unreadable > 0 {
+            eprintln!(
+                "WARNING: {} of {} blocks of {} could not be read and were zeroed",
+                unreadable,
+                loc.num_blocks,
+                output_path.display()
+            );
+        }
+    } else {
+        file.seek(SeekFrom::Start(start)).context("Failed to seek to the file data")?;
+        file.read_exact(&mut buffer).context("Failed to read the file data")?;
+    }
+
+    let mut outfile = std::fs::File::create(output_path).with_context(|| format!("Failed to create {}", output_path.display()))?;
+    outfile
+        .write_all(&buffer[..(loc.file_size as usize)])
+        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+
+    //the cache tables carry no dates, so a fixed time is the only way to get reproducible extractions
+    if let Some(mtime) = args.mtime {
+        filetime::set_file_handle_times(&outfile, None, Some(filetime::FileTime::from_unix_time(mtime, 0)))
+            .with_context(|| format!("Failed to set modification time of {}", output_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Checks that a loc describes a file that fits within its blocks, and blocks that fit within PSXCD.IMG,
+/// as garbage entries from a corrupt or misaligned cache would otherwise fail the whole extraction. With
+/// `zero_fill` the blocks past the end are left to be zeroed like any other block that can't be read.
+fn validate_loc(loc: &CdLoc, image_size: u64, zero_fill: bool) -> Result<()> {
+    let span = loc.num_blocks as u64 * 0x800;
+    if loc.file_size as u64 > span {
+        return Err(anyhow!("file size {} is larger than its {} blocks ({} bytes)", loc.file_size, loc.num_blocks, span));
+    }
+    let end = loc.start_block as u64 * 0x800 + span;
+    if end > image_size && !zero_fill {
+        return Err(anyhow!("blocks end at offset 0x{:X}, beyond the end of PSXCD.IMG (0x{:X} bytes)", end, image_size));
+    }
+    Ok(())
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+struct CdLoc {
+    start_block: u32,
+    num_blocks: u32,
+    file_size: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+struct CdName {
+    name: [u8; 32],
+}
+
+struct CDCache {
+    name_file_data: Vec<u8>,
+    loc_file_data: Vec<u8>,
+}
+
+impl CDCache {
+    fn names(&self) -> &[CdName] {
+        let size = std::mem::size_of::<CdName>();
+        let len = self.name_file_data.len() / size;
+        bytemuck::try_cast_slice(&self.name_file_data[..len * size]).expect("Buffer not aligned for CdName")
+    }
+    fn locs(&self) -> &[CdLoc] {
+        let size = std::mem::size_of::<CdLoc>();
+        let len = self.loc_file_data.len() / size;
+        bytemuck::try_cast_slice(&self.loc_file_data[..len * size]).expect("Buffer not aligned for CdLoc")
+    }
+}
+
+fn load_cd_cache(path: &str) -> Result<CDCache> {
+    let mut file_name = Path::new(path).join("PSXCDNAM.BIN");
+    let mut file = std::fs::File::open(&file_name).with_context(|| format!("Failed to open file: {}", file_name.display()))?;
+    let file_size = file.metadata()?.len() as usize;
+    let mut name_file_data = vec![0u8; file_size];
+    file.read_exact(&mut name_file_data).context("Failed to read file data")?;
+
+    file_name = Path::new(path).join("PSXCDLOC.BIN");
+    file = std::fs::File::open(&file_name).with_context(|| format!("Failed to open file: {}", file_name.display()))?;
+    let file_size = file.metadata()?.len() as usize;
+    let mut loc_file_data = vec![0u8; file_size];
+    file.read_exact(&mut loc_file_data).context("Failed to read file data")?;
+
+    Ok(CDCache {
+        name_file_data,
+        loc_file_data,
+    })
+}
+
+/// Reads the cache tables from inside PSXCD.IMG, for images where the cache files were not dumped.
+/// The table at `block` is laid out like PSXCDNAM.BIN followed directly by PSXCDLOC.BIN: name entries up to
+/// and including the first empty name, then one loc entry per name.
+fn load_embedded_cd_cache(file: &mut std::fs::File, block: u64) -> Result<CDCache> {
+    const MAX_ENTRIES: usize = 0x10000;
+    let name_size = std::mem::size_of::<CdName>();
+    let loc_size = std::mem::size_of::<CdLoc>();
+
+    file.seek(SeekFrom::Start(block * 0x800))
+        .with_context(|| format!("Failed to seek to embedded TOC at block {}", block))?;
+
+    let mut name_file_data = Vec::new();
+    let mut name = vec![0u8; name_size];
+    loop {
+        file.read_exact(&mut name).context("Failed to read embedded TOC name entry")?;
+        name_file_data.extend_from_slice(&name);
+        if name[0] == 0 {
+            break;
+        }
+        if name_file_data.len() / name_size >= MAX_ENTRIES {
+            return Err(anyhow!("Embedded TOC at block {} has no end of names marker", block));
+        }
+    }
+
+    let mut loc_file_data = vec![0u8; (name_file_data.len() / name_size) * loc_size];
+    file.read_exact(&mut loc_file_data)
+        .context("Failed to read embedded TOC loc entries")?;
+
+    Ok(CDCache {
+        name_file_data,
+        loc_file_data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(start_block: u32, num_blocks: u32, file_size: u32) -> CdLoc {
+        CdLoc {
+            start_block,
+            num_blocks,
+            file_size,
+        }
+    }
+
+    #[test]
+    fn check_failures_counts_skipped_locs() {
+        assert!(check_failures(0, 0).is_ok());
+        assert_eq!(check_failures(2, 0).unwrap_err().to_string(), "2 files could not be extracted");
+        assert_eq!(check_failures(0, 3).unwrap_err().to_string(), "3 files were skipped for invalid locs");
+        assert_eq!(
+            check_failures(2, 3).unwrap_err().to_string(),
+            "2 files could not be extracted, 3 more were skipped for invalid locs"
+        );
+    }
+
+    #[test]
+    fn validate_loc_accepts_blocks_within_the_image() {
+        assert!(validate_loc(&loc(1, 2, 0x1000), 0x1800, false).is_ok());
+    }
+
+    #[test]
+    fn validate_loc_rejects_a_file_larger_than_its_blocks() {
+        assert!(validate_loc(&loc(0, 1, 0x801), 0x1800, false).is_err());
+        assert!(validate_loc(&loc(0, 1, 0x801), 0x1800, true).is_err());
+    }
+
+    #[test]
+    fn validate_loc_leaves_blocks_past_the_end_to_zero_fill() {
+        assert!(validate_loc(&loc(2, 2, 0x1000), 0x1800, false).is_err());
+        assert!(validate_loc(&loc(2, 2, 0x1000), 0x1800, true).is_ok());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    ffi::OsString,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use common::{
+    args::{self, Help},
+    errors::{ErrorFormat, ErrorReport},
+    vprintln,
+};
+use gim::{GimImageHeader, ImageFormat, ImageOrder};
+use lexopt::{Arg, ValueExt};
+
+struct Args {
+    files: Vec<String>,
+    output_dir: Option<String>,
+    verbose: bool,
+    quiet: bool,
+    limit: Option<usize>,
+    format: Option<ImageFormat>,
+    swizzle: bool,
+    error_format: ErrorFormat,
+}
+
+/// The formats a PNG can be written as. The others would need the colours quantized or block compressed.
+const ENCODE_FORMATS: [ImageFormat; 3] = [ImageFormat::RGBA8888, ImageFormat::INDEX8, ImageFormat::INDEX4];
+
+const HELP: Help = Help {
+    name: "png2gim",
+    version: env!("CARGO_PKG_VERSION"),
+    usage: "[options] <files>...",
+    description: &["Converts PNG files to PSP GIM files, written as <name>.gim next to each PNG unless --output is given."],
+    options: &[
+        (
+            "--format <f>",
+            "Write the image as RGBA8888, INDEX8 or INDEX4 (default INDEX8 for indexed PNGs, RGBA8888 otherwise)",
+        ),
+        ("--swizzle", "Store the image in PSP tiled order instead of linear rows"),
+    ],
+    notes: &[
+        "INDEX8 and INDEX4 need an indexed PNG, the colours are not quantized. The palette is written as RGBA8888,",
+        "padded out to 256 or 16 entries with transparent black.",
+    ],
+    short_output: true,
+    threads: false,
+};
+
+fn parse_args<I>(args: I) -> Result<Args, lexopt::Error>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let mut format = None;
+    let mut swizzle = false;
+    let common = args::parse(args, &HELP, |arg, parser| {
+        match arg {
+            Arg::Long("format") => {
+                let value: ImageFormat = parser.value()?.parse()?;
+                if !ENCODE_FORMATS.contains(&value) {
+                    return Err(format!("can't write {} images, expected RGBA8888, INDEX8 or INDEX4", value).into());
+                }
+                format = Some(value);
+            }
+            Arg::Long("swizzle") => {
+                swizzle = true;
+            }
+            _ => return Err(arg.unexpected()),
+        }
+        Ok(())
+    })?;
+
+    if common.files.is_empty() {
+        eprintln!("Error: No input files specified.");
+        std::process::exit(1);
+    }
+
+    Ok(Args {
+        files: common.files,
+        output_dir: common.output,
+        verbose: common.verbose,
+        quiet: common.quiet,
+        limit: common.limit,
+        format,
+        swizzle,
+        error_format: common.error_format,
+    })
+}
+
+/// Runs png2gim with the given command line arguments (not including the program name).
+pub fn run<I>(args: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let args = parse_args(args).map_err(|e| anyhow!("Failed to parse command line: {}", e))?;
+    if let Some(output_dir) = &args.output_dir {
+        std::fs::create_dir_all(output_dir).with_context(|| format!("Failed to create output directory: {}", output_dir))?;
+    }
+
+    let filenames = &args.files[..args.limit.unwrap_or(usize::MAX).min(args.files.len())];
+    let mut failed = 0;
+    for filename in filenames {
+        if let Err(e) = convert_png(filename, &args) {
+            args.error_format.print(
+                format_args!("Error processing file {}: {:#}", filename, e),
+                ErrorReport {
+                    file: filename,
+                    kind: "encode",
+                    message: &format!("{:#}", e),
+                    offset: None,
+                },
+            );
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} files had errors", failed, filenames.len());
+    }
+    Ok(())
+}
+
+/// Converts one PNG file to a GIM, written as `<name>.gim`.
+fn convert_png(filename: &str, args: &Args) -> Result<()> {
+    let data = std::fs::read(filename).with_context(|| format!("Failed to read file: {}", filename))?;
+    let gim_data = encode_gim(filename, &data, args)?;
+    let output_path = output_path(filename, args);
+    std::fs::write(&output_path, gim_data).with_context(|| format!("Failed to write {}", output_path.display()))?;
+    vprintln!(!args.quiet, "Converted {} to {}", filename, output_path.display());
+    Ok(())
+}
+
+/// Encodes the PNG data as a GIM file, in the format and order the arguments ask for.
+fn encode_gim(filename: &str, data: &[u8], args: &Args) -> Result<Vec<u8>> {
+    let (width, height, rgba) = decode_rgba(data)?;
+    let indexed = decode_indexed(data)?;
+    let format = match args.format {
+        Some(format) => format,
+        None if indexed.is_some() => ImageFormat::INDEX8,
+        None => ImageFormat::RGBA8888,
+    };
+    let order = if args.swizzle {
+        ImageOrder::PSPImage
+    } else {
+        ImageOrder::Normal
+    };
+    vprintln!(args.verbose, "{}: {} x {}, writing as {} in {} order", filename, width, height, format, order);
+
+    let header = GimImageHeader::new(
+        format,
+        order,
+        u16::try_from(width).context("Image is too wide for a GIM")?,
+        u16::try_from(height).context("Image is too tall for a GIM")?,
+    );
+    //the data is laid out to the aligned size that gim2png reads it back with
+    let aligned_width = width.next_multiple_of(header.pitch_align as usize);
+    let aligned_height = height.next_multiple_of(header.height_align as usize);
+    let bpp = format.bits_per_pixel() as usize;
+    let pitch = aligned_width * bpp / 8;
+
+    let (pixels, palette) = match format {
+        ImageFormat::RGBA8888 => (pad_rows(&rgba, width * 4, height, pitch, aligned_height), None),
+        _ => {
+            let Some((indices, palette)) = indexed else {
+                bail!("{} needs an indexed PNG, the colours of an RGBA image are not quantized", format);
+            };
+            let entries = 1 << bpp;
+            if let Some(&index) = indices.iter().find(|&&index| index as usize >= entries) {
+                bail!("Palette index {} doesn't fit in {}, which has {} entries", index, format, entries);
+            }
+            let (packed, row_size) = if format == ImageFormat::INDEX4 {
+                //two pixels to a byte, the first in the low bits
+                let packed = indices
+                    .chunks_exact(width)
+                    .flat_map(|row| row.chunks(2).map(|pair| pair[0] | pair.get(1).copied().unwrap_or(0) << 4))
+                    .collect();
+                (packed, width.div_ceil(2))
+            } else {
+                (indices, width)
+            };
+            let mut palette = palette;
+            palette.resize(entries * 4, 0);
+            let palette_header = GimImageHeader::new(ImageFormat::RGBA8888, ImageOrder::Normal, entries as u16, 1);
+            (pad_rows(&packed, row_size, height, pitch, aligned_height), Some((palette_header, palette)))
+        }
+    };
+    let pixels = if args.swizzle {
+        gim::swizzle(&pixels, pitch, aligned_height, 16, 8).context("Failed to tile the image")?
+    } else {
+        pixels
+    };
+
+    Ok(gim::write_gim((header, &pixels), palette.as_ref().map(|(header, data)| (*header, data.as_slice()))))
+}
+
+/// `<name>.gim` in the output directory, or next to the input file when there is none.
+fn output_path(filename: &str, args: &Args) -> PathBuf {
+    let input = Path::new(filename);
+    let dir = match &args.output_dir {
+        Some(output_dir) => Path::new(output_dir),
+        None => input.parent().unwrap_or(Path::new("")),
+    };
+    dir.join(input.file_stem().unwrap_or(input.as_os_str())).with_extension("gim")
+}
+
+/// Copies rows of `row_size` bytes into rows of `pitch` bytes, padding the right of each row and the rows
+/// below the image out to `aligned_height` with zeros.
+fn pad_rows(data: &[u8], row_size: usize, height: usize, pitch: usize, aligned_height: usize) -> Vec<u8> {
+    let mut out = vec![0u8; pitch * aligned_height];
+    for (src, dst) in data.chunks_exact(row_size).take(height).zip(out.chunks_exact_mut(pitch)) {
+        dst[..row_size].copy_from_slice(src);
+    }
+    out
+}
+
+/// Decodes the PNG to 8 bit RGBA, whatever its colour type and depth.
+fn decode_rgba(data: &[u8]) -> Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16 | png::Transformations::ALPHA);
+    let mut reader = decoder.read_info().context("Failed to read PNG header")?;
+    let mut buffer = vec![0u8; reader.output_buffer_size().context("PNG is too large")?];
+    let info = reader.next_frame(&mut buffer).context("Failed to read PNG data")?;
+    buffer.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        color_type => bail!("Unexpected PNG colour type {:?} after expanding to RGBA", color_type),
+    };
+    Ok((info.width as usize, info.height as usize, rgba))
+}
+
+/// Reads the indices and palette of an indexed PNG, one byte per pixel and the palette as RGBA with the alpha
+/// from tRNS. `None` when the PNG is not indexed.
+fn decode_indexed(data: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().context("Failed to read PNG header")?;
+    let png_info = reader.info();
+    if png_info.color_type != png::ColorType::Indexed {
+        return Ok(None);
+    }
+    let plte = png_info.palette.as_deref().context("Indexed PNG has no palette")?;
+    let trns = png_info.trns.as_deref().unwrap_or_default();
+    let palette: Vec<u8> = plte
+        .chunks_exact(3)
+        .enumerate()
+        .flat_map(|(i, c)| [c[0], c[1], c[2], trns.get(i).copied().unwrap_or(255)])
+        .collect();
+    let depth = png_info.bit_depth as usize;
+
+    let mut buffer = vec![0u8; reader.output_buffer_size().context("PNG is too large")?];
+    let info = reader.next_frame(&mut buffer).context("Failed to read PNG data")?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    //below 8 bits the pixels are packed from the high bits down, and every row starts on a new byte
+    let mask = ((1u16 << depth) - 1) as u8;
+    let indices = buffer
+        .chunks_exact(info.line_size)
+        .take(height)
+        .flat_map(|row| {
+            (0..width).map(move |x| {
+                let bit = x * depth;
+                (row[bit / 8] >> (8 - depth - bit % 8)) & mask
+            })
+        })
+        .collect();
+    Ok(Some((indices, palette)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 3;
+
+    fn encode_png(color_type: png::ColorType, pixels: &[u8], palette: Option<(&[u8], &[u8])>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some((plte, trns)) = palette {
+            encoder.set_palette(plte.to_vec());
+            encoder.set_trns(trns.to_vec());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    /// Converts the PNG with the given arguments and reads back the image rows, without the padding, and palette.
+    fn round_trip(png_data: &[u8], extra_args: &[&str]) -> (ImageFormat, ImageOrder, Vec<u8>, Option<Vec<u8>>) {
+        let args = parse_args(extra_args.iter().copied().chain(["test.png"])).unwrap();
+        let gim_data = encode_gim("test.png", png_data, &args).unwrap();
+        let picture = gim::load_gim_image_owned(&gim_data).unwrap();
+        let header = picture.image_header;
+        let format = ImageFormat::try_from(header.format).unwrap();
+        let order = ImageOrder::try_from(header.order).unwrap();
+        assert_eq!((header.width as usize, header.height as usize), (WIDTH, HEIGHT));
+
+        let bpp = format.bits_per_pixel() as usize;
+        let pitch = (WIDTH.next_multiple_of(header.pitch_align as usize)) * bpp / 8;
+        let aligned_height = HEIGHT.next_multiple_of(header.height_align as usize);
+        let data = &picture.image_data[..pitch * aligned_height];
+        let linear = match order {
+            ImageOrder::PSPImage => gim::unswizzle(data, pitch, aligned_height, 16, 8).unwrap(),
+            _ => data.to_vec(),
+        };
+        let rows = linear
+            .chunks_exact(pitch)
+            .take(HEIGHT)
+            .flat_map(|row| &row[..WIDTH * bpp / 8])
+            .copied()
+            .collect();
+        (format, order, rows, picture.palette_data)
+    }
+
+    #[test]
+    fn rgba8888_round_trips() {
+        let pixels: Vec<u8> = (0..WIDTH * HEIGHT * 4).map(|i| (i * 13) as u8).collect();
+        let png_data = encode_png(png::ColorType::Rgba, &pixels, None);
+        for (extra_args, expected_order) in [(&[][..], ImageOrder::Normal), (&["--swizzle"][..], ImageOrder::PSPImage)] {
+            let (format, order, rows, palette) = round_trip(&png_data, extra_args);
+            assert_eq!((format, order), (ImageFormat::RGBA8888, expected_order));
+            assert_eq!(rows, pixels);
+            assert_eq!(palette, None);
+        }
+    }
+
+    #[test]
+    fn index8_round_trips() {
+        let indices: Vec<u8> = (0..WIDTH * HEIGHT).map(|i| (i % 3) as u8).collect();
+        let plte = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let trns = [255, 128, 0];
+        let png_data = encode_png(png::ColorType::Indexed, &indices, Some((&plte, &trns)));
+        for (extra_args, expected_order) in [(&[][..], ImageOrder::Normal), (&["--swizzle"][..], ImageOrder::PSPImage)] {
+            let (format, order, rows, palette) = round_trip(&png_data, extra_args);
+            assert_eq!((format, order), (ImageFormat::INDEX8, expected_order));
+            assert_eq!(rows, indices);
+            let palette = palette.unwrap();
+            assert_eq!(palette.len(), 256 * 4);
+            assert_eq!(&palette[..12], &[255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0]);
+            assert!(palette[12..].iter().all(|&b| b == 0));
+        }
+    }
+}
@@ -0,0 +1,3 @@
+fn main() -> anyhow::Result<()> {
+    png2gim::run(std::env::args_os().skip(1))
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result, bail};
+use lexopt::{Arg, Parser, ValueExt};
+
+fn print_usage() {
+    println!("Usage: psp-dstools <command> [options] <files>...");
+    println!("Commands:");
+    println!("  gim2png              convert PSP GIM files to PNG");
+    println!("  binextract           extract the entries of a .bin archive");
+    println!("  imgsplit             split the PSXCD.IMG file using its cache files");
+    println!("  png2gim              convert PNG files to PSP GIM files");
+    println!("Run 'psp-dstools <command> --help' for the options of each command.");
+}
+
+fn main() -> Result<()> {
+    let mut parser = Parser::from_env();
+    let command = match parser.next().context("Failed to parse command line")? {
+        Some(Arg::Value(val)) => val.string().context("Failed to parse command line")?,
+        Some(Arg::Long("help")) => {
+            print_usage();
+            return Ok(());
+        }
+        _ => {
+            print_usage();
+            std::process::exit(1);
+        }
+    };
+
+    //everything after the command belongs to the tool itself
+    let args = parser.raw_args().context("Failed to parse command line")?;
+    match command.as_str() {
+        "gim2png" => gim2png::run(args),
+        "binextract" => binextract::run(args),
+        "imgsplit" => imgsplit::run(args),
+        "png2gim" => png2gim::run(args),
+        _ => bail!("Unknown command '{}', run 'psp-dstools --help' for a list of commands", command),
+    }
+}